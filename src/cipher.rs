@@ -0,0 +1,145 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use std::sync::OnceLock;
+
+use crypto::aessafe::{AesSafe128Encryptor, AesSafe128Decryptor};
+use crypto::symmetriccipher::{BlockEncryptor, BlockDecryptor};
+
+#[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+use crypto::aes::KeySize;
+#[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+use crypto::aesni::{AesNiEncryptor, AesNiDecryptor};
+
+//-----------------------------------------------------------------------------------------------------------
+// AesBackend (AES-128 block cipher for the CBC streams of RN_V1 records and FnAdaptor)
+// NOTE: AesGcm already selects AES-NI at runtime, only the CBC paths need the backend.
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AesBackend {
+    AesNi,
+    Portable // constant-time bitsliced software AES
+}
+
+impl AesBackend {
+    // detected once, AES-NI requires the "aesni" feature and CPU support
+    pub fn detect() -> Self {
+        static BACKEND: OnceLock<AesBackend> = OnceLock::new();
+        *BACKEND.get_or_init(|| if supports_aesni() { AesBackend::AesNi } else { AesBackend::Portable })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AesBackend::AesNi => "aes-ni",
+            AesBackend::Portable => "portable"
+        }
+    }
+
+    // falls back to the portable backend if AES-NI is not available
+    pub fn encryptor(&self, key: &[u8]) -> Aes128Encryptor {
+        #[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+        if *self == AesBackend::AesNi && supports_aesni() {
+            return Aes128Encryptor::AesNi(AesNiEncryptor::new(KeySize::KeySize128, key))
+        }
+
+        Aes128Encryptor::Portable(AesSafe128Encryptor::new(key))
+    }
+
+    pub fn decryptor(&self, key: &[u8]) -> Aes128Decryptor {
+        #[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+        if *self == AesBackend::AesNi && supports_aesni() {
+            return Aes128Decryptor::AesNi(AesNiDecryptor::new(KeySize::KeySize128, key))
+        }
+
+        Aes128Decryptor::Portable(AesSafe128Decryptor::new(key))
+    }
+}
+
+#[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+fn supports_aesni() -> bool {
+    is_x86_feature_detected!("aes") && is_x86_feature_detected!("sse2")
+}
+
+#[cfg(not(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64"))))]
+fn supports_aesni() -> bool {
+    false
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Block ciphers of the selected backend
+//-----------------------------------------------------------------------------------------------------------
+pub enum Aes128Encryptor {
+    #[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+    AesNi(AesNiEncryptor),
+    Portable(AesSafe128Encryptor)
+}
+
+impl BlockEncryptor for Aes128Encryptor {
+    fn block_size(&self) -> usize {
+        16
+    }
+
+    fn encrypt_block(&self, input: &[u8], output: &mut [u8]) {
+        match self {
+            #[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+            Aes128Encryptor::AesNi(e) => e.encrypt_block(input, output),
+            Aes128Encryptor::Portable(e) => e.encrypt_block(input, output)
+        }
+    }
+}
+
+pub enum Aes128Decryptor {
+    #[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+    AesNi(AesNiDecryptor),
+    Portable(AesSafe128Decryptor)
+}
+
+impl BlockDecryptor for Aes128Decryptor {
+    fn block_size(&self) -> usize {
+        16
+    }
+
+    fn decrypt_block(&self, input: &[u8], output: &mut [u8]) {
+        match self {
+            #[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+            Aes128Decryptor::AesNi(d) => d.decrypt_block(input, output),
+            Aes128Decryptor::Portable(d) => d.decrypt_block(input, output)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use aesstream::{AesWriter, AesReader};
+
+    #[test]
+    fn backend_compatibility() {
+        let key = b"encryption123456";
+        let plaintext = vec![7u8; 1000];
+
+        // ciphertexts of any backend can be read by the other
+        let backends = [AesBackend::AesNi, AesBackend::Portable];
+        for enc in backends.iter() {
+            let mut ciphertext = Vec::new();
+            let mut writer = AesWriter::new(&mut ciphertext, enc.encryptor(key)).unwrap();
+            writer.write_all(&plaintext).unwrap();
+            drop(writer);
+
+            for dec in backends.iter() {
+                let mut reader = AesReader::new(ciphertext.as_slice(), dec.decryptor(key)).unwrap();
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data).unwrap();
+                assert!(data == plaintext);
+            }
+        }
+
+        // the block outputs are identical
+        let (mut b1, mut b2) = ([0u8; 16], [0u8; 16]);
+        AesBackend::AesNi.encryptor(key).encrypt_block(b"0123456789abcdef", &mut b1);
+        AesBackend::Portable.encryptor(key).encrypt_block(b"0123456789abcdef", &mut b2);
+        assert!(b1 == b2);
+    }
+}
@@ -0,0 +1,220 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use crate::crypto::KeyPair;
+use crate::crypto::shares::*;
+use crate::curator::*;
+use crate::structs::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// RecoveryReport
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CuratorStatus {
+    Valid, // share used in the recovery
+    Invalid, // share rejected by the proof
+    Failed(String), // unreachable, timeout or error reply
+    Pending // not queried or not needed
+}
+
+#[derive(Debug, Clone)]
+pub struct CuratorReport {
+    pub addr: String,
+    pub i: Option<u32>,
+    pub status: CuratorStatus
+}
+
+pub struct RecoveryReport {
+    pub files: Vec<RnFileRef>,
+    pub curators: Vec<CuratorReport>
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// RecoveryClient (queries t+1 curators in parallel, the next ones replace failed, invalid or stalled answers)
+//-----------------------------------------------------------------------------------------------------------
+pub struct RecoveryClient {
+    pub curators: Vec<String>,
+    pub timeout: Duration
+}
+
+impl RecoveryClient {
+    pub fn new(curators: Vec<String>, timeout: Duration) -> Self {
+        Self { curators, timeout }
+    }
+
+    // "commit" is the trusted commitment of the master key, answers are checked against it
+    // NOTE: a consent token is only usable with the secret key of its grantee.
+    pub fn recover(&self, chain: &RnChain, commit: &RistrettoPolynomial, auth: &Authorization, grantee: Option<&KeyPair>) -> Result<RecoveryReport> {
+        let threshold = commit.A.len();
+        let kn = *chain.kn();
+        if let (Authorization::Consent(_), None) = (auth, grantee) {
+            return Err(Error::InvalidParameter("Grantee key required!"))
+        }
+
+        let mut curators: Vec<CuratorReport> = self.curators.iter()
+            .map(|addr| CuratorReport { addr: addr.clone(), i: None, status: CuratorStatus::Pending })
+            .collect();
+
+        let query = PartialRequest { id: chain.id().into(), set: chain.set().into(), auth: auth.clone() };
+        let (tx, rx) = mpsc::channel();
+        let send = |k: usize| {
+            let (tx, addr, query, timeout) = (tx.clone(), self.curators[k].clone(), query.clone(), self.timeout);
+            std::thread::spawn(move || {
+                let res = call::<_, ProvedRistrettoShare>(&addr, "/partial", Some(&query), timeout);
+                tx.send((k, res)).ok();
+            });
+        };
+
+        let mut next = std::cmp::min(threshold, self.curators.len());
+        (0..next).for_each(send);
+
+        // each call is bounded by the timeout (connect, write and read)
+        let mut running = next;
+        let mut valid = Vec::<RistrettoShare>::new();
+        while valid.len() < threshold && running > 0 {
+            let (k, res) = match rx.recv_timeout(3 * self.timeout) {
+                Ok(msg) => msg,
+                Err(_) if next < self.curators.len() => {
+                    // the running calls are stalled, they can still answer while the next curators are queried
+                    for c in curators[..next].iter_mut().filter(|c| c.status == CuratorStatus::Pending) {
+                        c.status = CuratorStatus::Failed("Timeout!".into());
+                    }
+
+                    let end = std::cmp::min(next + threshold - valid.len(), self.curators.len());
+                    (next..end).for_each(send);
+                    running += end - next;
+                    next = end;
+                    continue
+                },
+                Err(_) => break
+            };
+
+            running -= 1;
+            match res {
+                Ok(proved) => {
+                    curators[k].i = Some(proved.share.i);
+                    if Self::is_valid(&proved, &kn, commit, &valid) {
+                        curators[k].status = CuratorStatus::Valid;
+                        valid.push(proved.share);
+                        continue
+                    }
+
+                    curators[k].status = CuratorStatus::Invalid;
+                },
+                Err(e) => curators[k].status = CuratorStatus::Failed(e.to_string())
+            }
+
+            // replace the failed answer
+            if next < self.curators.len() {
+                send(next);
+                next += 1;
+                running += 1;
+            }
+        }
+
+        if valid.len() < threshold {
+            let faulty = curators.iter().filter(|c| c.status == CuratorStatus::Invalid).filter_map(|c| c.i).collect();
+            return Err(Error::NotEnoughShares { faulty })
+        }
+
+        let alpha = RistrettoShareVector(valid).recover();
+        let alpha = match (auth, grantee) {
+            (Authorization::Consent(token), Some(grantee)) => token.alpha(grantee, &alpha),
+            _ => alpha.compress()
+        };
+
+        let files = chain.recover(&alpha)?;
+
+        Ok(RecoveryReport { files, curators })
+    }
+
+    fn is_valid(proved: &ProvedRistrettoShare, kn: &RistrettoPoint, commit: &RistrettoPolynomial, valid: &[RistrettoShare]) -> bool {
+        proved.verify(kn, commit) && !valid.iter().any(|s| s.i == proved.share.i)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::dkg::{DkgOutput, federation};
+    use crate::curator::tests::*;
+    use crate::consent::AccessRequest;
+    use crate::store::RnStore;
+
+    #[test]
+    fn client_recover() {
+        let skp = KeyPair::new(); // source key-pair
+        let gkp = KeyPair::new(); // grantee key-pair
+        let timeout = Duration::from_secs(2);
+
+        let mut outputs = federation(1, 3);
+        let ekey = outputs[0].key();
+        let commit = outputs[0].commit.clone();
+
+        // a byzantine curator answering with a share of another federation
+        let other = federation(1, 3);
+        outputs[0] = DkgOutput { share: other[0].share.clone(), commit: commit.clone() };
+
+        let dirs: Vec<_> = (0..3).map(|_| tmp_dir()).collect();
+        let nodes: Vec<CuratorNode> = outputs.into_iter().zip(dirs.iter())
+            .map(|(o, dir)| Curator::new(o, RnStore::open(dir).unwrap()).unwrap().with_grantees(vec![gkp.key]).spawn("127.0.0.1:0").unwrap())
+            .collect();
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, head) = Rn::head(&skp, &ekey, "subject-id", "dataset-id", rd.clone());
+        let chain = RnChain::new(head.clone()).unwrap();
+
+        for node in nodes.iter() {
+            let req = AppendRequest { id: "subject-id".into(), set: "dataset-id".into(), rn: head.clone() };
+            call::<_, AppendResponse>(&node.addr().to_string(), "/append", Some(&req), timeout).unwrap();
+        }
+
+        // unreachable, byzantine and two correct curators
+        let mut addrs = vec!["127.0.0.1:1".to_string()];
+        addrs.extend(nodes.iter().map(|n| n.addr().to_string()));
+
+        let request = AccessRequest::new(&gkp, &chain, unix_now());
+        let auth = Authorization::Access(Box::new(request));
+
+        let client = RecoveryClient::new(addrs.clone(), timeout);
+        let report = client.recover(&chain, &commit, &auth, None).unwrap();
+        assert!(report.files[0] == rd.file);
+
+        let status: Vec<_> = report.curators.iter().map(|c| c.status.clone()).collect();
+        assert!(matches!(status[0], CuratorStatus::Failed(_)));
+        assert!(status[1..] == [CuratorStatus::Invalid, CuratorStatus::Valid, CuratorStatus::Valid]);
+
+        // not enough valid curators
+        let client = RecoveryClient::new(addrs[..3].to_vec(), timeout);
+        assert!(matches!(client.recover(&chain, &commit, &auth, None), Err(Error::NotEnoughShares { faulty }) if faulty == vec![1]));
+
+        // a curator that keeps the connection alive without answering is replaced
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for _ in 0..20 {
+                std::thread::sleep(Duration::from_millis(300));
+                if std::io::Write::write_all(&mut stream, b" ").is_err() {
+                    break
+                }
+            }
+        });
+
+        let client = RecoveryClient::new(vec![slow, addrs[2].clone(), addrs[3].clone()], Duration::from_secs(1));
+        let report = client.recover(&chain, &commit, &auth, None).unwrap();
+        let status: Vec<_> = report.curators.iter().map(|c| c.status.clone()).collect();
+        assert!(status == [CuratorStatus::Failed("Timeout!".into()), CuratorStatus::Valid, CuratorStatus::Valid]);
+
+        drop(nodes);
+        for dir in dirs {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
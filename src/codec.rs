@@ -0,0 +1,56 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::errors::Result;
+
+//-----------------------------------------------------------------------------------------------------------
+// Serde formats for the exchanged structures (bincode is always available)
+//-----------------------------------------------------------------------------------------------------------
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(value)?)
+}
+
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    Ok(bincode::deserialize(data)?)
+}
+
+#[cfg(feature = "json")]
+pub fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+#[cfg(feature = "json")]
+pub fn from_json<T: DeserializeOwned>(data: &str) -> Result<T> {
+    Ok(serde_json::from_str(data)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::structs::*;
+
+    #[test]
+    fn codec_roundtrip() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd);
+
+        let r2: Rn = decode(&encode(&r).unwrap()).unwrap();
+        assert!(r2.check().unwrap() == r.hash());
+
+        #[cfg(feature = "json")] {
+            let r3: Rn = from_json(&to_json(&r).unwrap()).unwrap();
+            assert!(r3.check().unwrap() == r.hash());
+            assert!(matches!(from_json::<Rn>("{"), Err(crate::errors::Error::Codec(_))));
+        }
+
+        assert!(matches!(decode::<Rn>(&[1, 2, 3]), Err(crate::errors::Error::Codec(_))));
+    }
+}
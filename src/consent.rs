@@ -0,0 +1,249 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use sha2::{Sha512, Digest};
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::ristretto::{RistrettoPoint, CompressedRistretto};
+
+use crate::crypto::*;
+use crate::crypto::proofs::DleqProof;
+use crate::crypto::signatures::*;
+use crate::structs::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// ConsentToken (the data-subject part of alpha, only usable by the grantee)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConsentToken {
+    pub id: String,
+    pub set: String,
+    #[serde(with = "crate::crypto::b64")]
+    pub kn: RistrettoPoint,
+    #[serde(with = "crate::crypto::b64")]
+    pub grantee: RistrettoPoint,
+    pub expires: u64, // unix time in seconds
+    #[serde(with = "crate::crypto::b64")]
+    pub partial: RistrettoPoint, // subject * (kn + grantee)
+    pub proof: DleqProof,
+    sig: ExtSignature
+}
+
+impl ConsentToken {
+    pub fn new(subject: &KeyPair, chain: &RnChain, grantee: &RistrettoPoint, expires: u64) -> Self {
+        let kn = *chain.kn();
+        let base = kn + grantee;
+        let partial = subject.s * base;
+        let proof = DleqProof::prove(&subject.s, &base, &subject.key, &partial);
+
+        let dhash = Self::hash(chain.id(), chain.set(), &kn, grantee, expires, &partial);
+        let sig = ExtSignature::sign(&subject.s, subject.key, &dhash);
+
+        Self { id: chain.id().into(), set: chain.set().into(), kn, grantee: *grantee, expires, partial, proof, sig }
+    }
+
+    pub fn subject(&self) -> &RistrettoPoint {
+        &self.sig.key
+    }
+
+    // checked by the curators before releasing "yi * kn"
+    pub fn verify(&self, chain: &RnChain, now: u64) -> Result<()> {
+        let subject = chain.subject().ok_or(Error::NotOwned)?;
+        if subject != self.subject() || self.id != chain.id() || self.set != chain.set() || &self.kn != chain.kn() {
+            return Err(Error::Mismatch)
+        }
+
+        if now > self.expires {
+            return Err(Error::Expired)
+        }
+
+        let dhash = Self::hash(&self.id, &self.set, &self.kn, &self.grantee, self.expires, &self.partial);
+        if !self.sig.verify(&dhash) {
+            return Err(Error::InvalidSignature)
+        }
+
+        if !self.proof.verify(&(self.kn + self.grantee), subject, &self.partial) {
+            return Err(Error::InvalidProof)
+        }
+
+        Ok(())
+    }
+
+    // "curators" is the recovered "s * kn" of the federation key
+    pub fn alpha(&self, grantee: &KeyPair, curators: &RistrettoPoint) -> CompressedRistretto {
+        let part = self.partial - grantee.s * self.subject();
+        (curators + part).compress()
+    }
+
+    fn hash(id: &str, set: &str, kn: &RistrettoPoint, grantee: &RistrettoPoint, expires: u64, partial: &RistrettoPoint) -> Vec<u8> {
+        Sha512::new()
+            .chain(id)
+            .chain(set)
+            .chain(kn.compress().as_bytes())
+            .chain(grantee.compress().as_bytes())
+            .chain(expires.to_le_bytes())
+            .chain(partial.compress().as_bytes())
+            .result().to_vec()
+    }
+}
+
+pub const ACCESS_WINDOW: u64 = 300; // max age of a request in seconds
+
+//-----------------------------------------------------------------------------------------------------------
+// AccessRequest (ordinary recovery of a chain without a data-subject, signed by a grantee of the curators)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessRequest {
+    pub id: String,
+    pub set: String,
+    #[serde(with = "crate::crypto::b64")]
+    pub kn: RistrettoPoint,
+    pub timestamp: u64, // unix time in seconds
+    sig: ExtSignature
+}
+
+impl AccessRequest {
+    pub fn new(grantee: &KeyPair, chain: &RnChain, timestamp: u64) -> Self {
+        let kn = *chain.kn();
+        let dhash = Self::hash(chain.id(), chain.set(), &kn, timestamp);
+        let sig = ExtSignature::sign(&grantee.s, grantee.key, &dhash);
+
+        Self { id: chain.id().into(), set: chain.set().into(), kn, timestamp, sig }
+    }
+
+    pub fn grantee(&self) -> &RistrettoPoint {
+        &self.sig.key
+    }
+
+    // "grantees" are the keys allowed to recover, configured by the curator.
+    // Owned chains need a ConsentToken of the data-subject instead.
+    pub fn verify(&self, chain: &RnChain, grantees: &[RistrettoPoint], now: u64) -> Result<()> {
+        if chain.subject().is_some() {
+            return Err(Error::SubjectRequired)
+        }
+
+        if self.id != chain.id() || self.set != chain.set() || &self.kn != chain.kn() {
+            return Err(Error::Mismatch)
+        }
+
+        if self.timestamp > now || now - self.timestamp > ACCESS_WINDOW {
+            return Err(Error::Expired)
+        }
+
+        if !grantees.contains(self.grantee()) {
+            return Err(Error::UnknownRequester)
+        }
+
+        let dhash = Self::hash(&self.id, &self.set, &self.kn, self.timestamp);
+        if !self.sig.verify(&dhash) {
+            return Err(Error::InvalidSignature)
+        }
+
+        Ok(())
+    }
+
+    // tagged, not interchangeable with an emergency request
+    fn hash(id: &str, set: &str, kn: &RistrettoPoint, timestamp: u64) -> Vec<u8> {
+        Sha512::new()
+            .chain(b"access")
+            .chain(id)
+            .chain(set)
+            .chain(kn.compress().as_bytes())
+            .chain(timestamp.to_le_bytes())
+            .result().to_vec()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consent_recover() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+        let dkp = KeyPair::new(); // data-subject key-pair
+        let gkp = KeyPair::new(); // grantee key-pair
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-1-url".to_vec() } };
+        let (lamb, r) = Rn::owned_head(&skp, &ekp.key, &dkp.key, "subject-id", "dataset-id", rd);
+        let mut chain = RnChain::new(r).unwrap();
+
+        let rd = RnData { lambda_prev: Some(lamb), file: RnFileRef { dn: *b"encryption654321", hfile: b"file-2-url".to_vec() } };
+        let (_, r) = chain.tail(&skp, &ekp.key, rd);
+        chain.push(r).unwrap();
+
+        // the curators can't recover alone
+        let curators = ekp.s * chain.kn();
+        assert!(chain.recover(&curators.compress()).is_err());
+
+        // direct cooperation of the data-subject
+        let alpha = (curators + dkp.s * chain.kn()).compress();
+        assert!(chain.recover(&alpha).unwrap().len() == 2);
+
+        // consent token for the grantee
+        let token = ConsentToken::new(&dkp, &chain, &gkp.key, 1000);
+        assert!(token.verify(&chain, 999).is_ok());
+        assert!(token.verify(&chain, 1001).is_err());
+
+        let refs = chain.recover(&token.alpha(&gkp, &curators)).unwrap();
+        assert!(refs[1].hfile == b"file-2-url");
+
+        // the token is useless for anyone else
+        let other = KeyPair::new();
+        assert!(chain.recover(&token.alpha(&other, &curators)).is_err());
+    }
+
+    #[test]
+    fn consent_invalid() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+        let dkp = KeyPair::new(); // data-subject key-pair
+        let gkp = KeyPair::new(); // grantee key-pair
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, r) = Rn::owned_head(&skp, &ekp.key, &dkp.key, "subject-id", "dataset-id", rd.clone());
+        let chain = RnChain::new(r).unwrap();
+
+        // signed by someone else
+        let token = ConsentToken::new(&KeyPair::new(), &chain, &gkp.key, 1000);
+        assert!(token.verify(&chain, 0).is_err());
+
+        // a modified partial
+        let mut token = ConsentToken::new(&dkp, &chain, &gkp.key, 1000);
+        token.partial += G;
+        assert!(matches!(token.verify(&chain, 0), Err(Error::InvalidSignature)));
+
+        // not an owned chain
+        let (_, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd);
+        let chain = RnChain::new(r).unwrap();
+        let token = ConsentToken::new(&dkp, &chain, &gkp.key, 1000);
+        assert!(token.verify(&chain, 0).is_err());
+    }
+
+    #[test]
+    fn access_request() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+        let gkp = KeyPair::new(); // grantee key-pair
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd.clone());
+        let chain = RnChain::new(r).unwrap();
+
+        let request = AccessRequest::new(&gkp, &chain, 1000);
+        assert!(request.verify(&chain, &[gkp.key], 1010).is_ok());
+        assert!(matches!(request.verify(&chain, &[gkp.key], 1000 + ACCESS_WINDOW + 1), Err(Error::Expired)));
+
+        // signed by a key unknown to the curator
+        let request = AccessRequest::new(&KeyPair::new(), &chain, 1000);
+        assert!(matches!(request.verify(&chain, &[gkp.key], 1000), Err(Error::UnknownRequester)));
+
+        // owned chains require the consent of the data-subject
+        let (_, r) = Rn::owned_head(&skp, &ekp.key, &KeyPair::new().key, "subject-id", "dataset-id", rd);
+        let owned = RnChain::new(r).unwrap();
+        let request = AccessRequest::new(&gkp, &owned, 1000);
+        assert!(matches!(request.verify(&owned, &[gkp.key], 1000), Err(Error::SubjectRequired)));
+    }
+}
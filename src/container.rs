@@ -0,0 +1,595 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use sha2::{Sha512, Digest};
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+use std::io::{Read, Write, Seek, SeekFrom};
+
+use rand::RngCore;
+
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{AeadEncryptor, AeadDecryptor};
+
+use crate::crypto::*;
+use crate::crypto::signatures::*;
+use crate::errors::{Error, Result};
+
+pub const FN_MAGIC: [u8; 4] = *b"FNC1";
+pub const FN_VERSION: u8 = 1;
+pub const FN_SEGMENT: usize = 64 * 1024;
+
+const FN_MAX_SEGMENT: u32 = 16 * 1024 * 1024;
+const FN_FIXED_SIZE: u64 = 4 + 1 + 16 + 4 + 8; // magic, version, file_id, seg_size, len
+const FN_INDEX_SIZE: u64 = 12 + 16; // NOTE: bincode size for FnSegment
+const FN_SIG_SIZE: u64 = 136; // NOTE: bincode size for ExtSignature
+const FN_WORKER_SEGMENTS: usize = 16; // segments processed by each worker in a batch
+
+//-----------------------------------------------------------------------------------------------------------
+// FnHeader (fixed-size segments, the index holds the nonce and tag of each segment)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct FnSegment {
+    pub nonce: [u8; 12],
+    pub tag: [u8; 16]
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FnHeader {
+    pub magic: [u8; 4],
+    pub version: u8,
+    pub file_id: [u8; 16],
+    pub seg_size: u32,
+    pub len: u64,
+    pub index: Vec<FnSegment>
+}
+
+impl FnHeader {
+    pub fn segments(len: u64, seg_size: u32) -> u64 {
+        len.div_ceil(u64::from(seg_size))
+    }
+
+    // serialized size, followed by the signature of the hash
+    pub fn size(&self) -> u64 {
+        FN_FIXED_SIZE + 8 + FN_INDEX_SIZE * self.index.len() as u64 + FN_SIG_SIZE
+    }
+
+    // the signature binds the source to the dn key and every segment tag
+    pub fn hash(&self, dn: &[u8; 16]) -> Vec<u8> {
+        let mut hasher = Sha512::new()
+            .chain(dn)
+            .chain(self.magic)
+            .chain([self.version])
+            .chain(self.file_id)
+            .chain(self.seg_size.to_le_bytes())
+            .chain(self.len.to_le_bytes());
+
+        for seg in self.index.iter() {
+            hasher = hasher
+                .chain(seg.nonce)
+                .chain(seg.tag);
+        }
+
+        hasher.result().to_vec()
+    }
+
+    fn aad(&self, k: u64) -> Vec<u8> {
+        let data: &[&[u8]] = &[&self.file_id, &k.to_le_bytes()];
+        data.concat()
+    }
+
+    fn seg_len(&self, k: u64) -> usize {
+        let seg_size = u64::from(self.seg_size);
+        std::cmp::min(seg_size, self.len - k * seg_size) as usize
+    }
+
+    fn encrypt(&self, dn: &[u8; 16], k: u64, plain: &[u8]) -> (FnSegment, Vec<u8>) {
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut cipher = vec![0u8; plain.len()];
+        let mut tag = [0u8; 16];
+        let mut encryptor = AesGcm::new(KeySize::KeySize128, dn, &nonce, &self.aad(k));
+        encryptor.encrypt(plain, &mut cipher, &mut tag);
+
+        (FnSegment { nonce, tag }, cipher)
+    }
+
+    fn decrypt(&self, dn: &[u8; 16], k: u64, cipher: &[u8]) -> Option<Vec<u8>> {
+        let seg = &self.index[k as usize];
+        let mut plain = vec![0u8; cipher.len()];
+        let mut decryptor = AesGcm::new(KeySize::KeySize128, dn, &seg.nonce, &self.aad(k));
+        if !decryptor.decrypt(cipher, &mut plain, &seg.tag) {
+            return None
+        }
+
+        Some(plain)
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// FnWriter (the plaintext length is known in advance so that the index can be placed in the header)
+//-----------------------------------------------------------------------------------------------------------
+pub struct FnWriter<W: Write + Seek> {
+    s: Scalar,
+    key: RistrettoPoint,
+    dn: [u8; 16],
+    to: W,
+    start: u64,
+    header: FnHeader,
+    written: u64,
+    buf: Vec<u8>
+}
+
+impl<W: Write + Seek> FnWriter<W> {
+    pub fn new(keyp: &KeyPair, dn: &[u8; 16], len: u64, to: W) -> Result<Self> {
+        Self::with_segment(keyp, dn, len, FN_SEGMENT as u32, to)
+    }
+
+    pub fn with_segment(keyp: &KeyPair, dn: &[u8; 16], len: u64, seg_size: u32, mut to: W) -> Result<Self> {
+        if seg_size == 0 || seg_size > FN_MAX_SEGMENT {
+            return Err(Error::InvalidParameter("Invalid segment size!"))
+        }
+
+        let mut file_id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut file_id);
+
+        let n = FnHeader::segments(len, seg_size) as usize;
+        let header = FnHeader { magic: FN_MAGIC, version: FN_VERSION, file_id, seg_size, len, index: vec![FnSegment::default(); n] };
+
+        // reserve space for the header, it's written on finish
+        let start = to.stream_position()?;
+        to.write_all(&vec![0u8; header.size() as usize])?;
+
+        Ok(Self { s: keyp.s, key: keyp.key, dn: *dn, to, start, header, written: 0, buf: Vec::with_capacity(seg_size as usize) })
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        if !self.buf.is_empty() {
+            self.flush_segment()?;
+        }
+
+        if self.written != self.header.len {
+            return Err(Error::InvalidParameter("Incorrect file length!"))
+        }
+
+        // signed once the index is complete
+        let sig = ExtSignature::sign(&self.s, self.key, &self.header.hash(&self.dn));
+
+        let end = self.to.stream_position()?;
+        self.to.seek(SeekFrom::Start(self.start))?;
+        bincode::serialize_into(&mut self.to, &(&self.header, &sig))?;
+        self.to.seek(SeekFrom::Start(end))?;
+        self.to.flush()?;
+
+        Ok(self.to)
+    }
+
+    fn flush_segment(&mut self) -> Result<()> {
+        let k = (self.written - self.buf.len() as u64) / u64::from(self.header.seg_size);
+        let (seg, cipher) = self.header.encrypt(&self.dn, k, &self.buf);
+
+        self.to.write_all(&cipher)?;
+        self.header.index[k as usize] = seg;
+        self.buf.clear();
+
+        Ok(())
+    }
+
+    // encrypt the next segments in parallel, "data" must end on a segment boundary or at the end of the file
+    fn write_parallel(&mut self, data: &[u8], workers: usize) -> Result<()> {
+        let seg_size = self.header.seg_size as usize;
+        if !self.buf.is_empty() || self.written + data.len() as u64 > self.header.len {
+            return Err(Error::InvalidParameter("Incorrect file length!"))
+        }
+
+        let k0 = self.written / seg_size as u64;
+        let groups: Vec<&[u8]> = data.chunks(seg_size * FN_WORKER_SEGMENTS).collect();
+        let (header, dn) = (&self.header, &self.dn);
+        let res = parallel(&groups, workers, |g, group| {
+            let first = k0 + (g * FN_WORKER_SEGMENTS) as u64;
+            group.chunks(seg_size).enumerate()
+                .map(|(j, plain)| header.encrypt(dn, first + j as u64, plain))
+                .collect::<Vec<_>>()
+        });
+
+        for (j, (seg, cipher)) in res.into_iter().flatten().enumerate() {
+            self.to.write_all(&cipher)?;
+            self.header.index[k0 as usize + j] = seg;
+        }
+
+        self.written += data.len() as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Write for FnWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let free = self.header.seg_size as usize - self.buf.len();
+        let n = std::cmp::min(free, data.len());
+        if self.written + n as u64 > self.header.len {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, Error::InvalidParameter("Incorrect file length!")))
+        }
+
+        self.buf.extend_from_slice(&data[..n]);
+        self.written += n as u64;
+
+        if self.buf.len() == self.header.seg_size as usize {
+            self.flush_segment()?;
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.to.flush()
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// FnReader (random access decryption of single segments)
+//-----------------------------------------------------------------------------------------------------------
+pub struct FnReader<R: Read + Seek> {
+    dn: [u8; 16],
+    from: R,
+    start: u64,
+    pos: u64,
+    pub header: FnHeader,
+    cache: Option<(u64, Vec<u8>)>
+}
+
+impl<R: Read + Seek> FnReader<R> {
+    // "source" is the expected signer, checked before any segment is decrypted
+    pub fn open(source: &RistrettoPoint, dn: &[u8; 16], mut from: R) -> Result<Self> {
+        let mut fixed = [0u8; FN_FIXED_SIZE as usize];
+        from.read_exact(&mut fixed)?;
+
+        if fixed[0..4] != FN_MAGIC || fixed[4] != FN_VERSION {
+            return Err(Error::UnsupportedVersion)
+        }
+
+        let seg_size = u32::from_le_bytes(*arrayref::array_ref!(fixed, 21, 4));
+        let len = u64::from_le_bytes(*arrayref::array_ref!(fixed, 25, 8));
+        if seg_size == 0 || seg_size > FN_MAX_SEGMENT {
+            return Err(Error::InvalidParameter("Invalid segment size!"))
+        }
+
+        // read the index and signature, the unsigned "len" is bounded by the file length
+        let n = FnHeader::segments(len, seg_size);
+        let pos = from.stream_position()?;
+        let end = from.seek(SeekFrom::End(0))?;
+        from.seek(SeekFrom::Start(pos))?;
+
+        let size = FN_INDEX_SIZE.checked_mul(n)
+            .and_then(|s| s.checked_add(8 + FN_SIG_SIZE))
+            .filter(|s| *s <= end.saturating_sub(pos))
+            .ok_or(Error::Truncated)?;
+
+        let mut rest = Vec::new();
+        from.by_ref().take(size).read_to_end(&mut rest)?;
+        if rest.len() as u64 != size {
+            return Err(Error::Truncated)
+        }
+
+        let (header, sig): (FnHeader, ExtSignature) = bincode::deserialize(&[&fixed[..], &rest].concat())?;
+        if header.index.len() as u64 != n || !sig.verify(&header.hash(dn)) {
+            return Err(Error::InvalidSignature)
+        }
+
+        if &sig.key != source {
+            return Err(Error::UnexpectedSigner)
+        }
+
+        let start = from.stream_position()?;
+        Ok(Self { dn: *dn, from, start, pos: 0, header, cache: None })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.header.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.len == 0
+    }
+
+    pub fn segment(&mut self, k: u64) -> Result<&[u8]> {
+        if k >= self.header.index.len() as u64 {
+            return Err(Error::InvalidParameter("Segment out of range!"))
+        }
+
+        let cached = match self.cache.as_ref() {
+            Some((ck, _)) => *ck == k,
+            None => false
+        };
+
+        if !cached {
+            let mut cipher = vec![0u8; self.header.seg_len(k)];
+            self.from.seek(SeekFrom::Start(self.start + k * u64::from(self.header.seg_size)))?;
+            self.from.read_exact(&mut cipher)?;
+
+            let plain = self.header.decrypt(&self.dn, k, &cipher).ok_or(Error::DecryptionFailed)?;
+            self.cache = Some((k, plain));
+        }
+
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+
+    // decrypt all the segments in parallel batches
+    fn read_parallel<W: Write>(&mut self, mut to: W, workers: usize) -> Result<()> {
+        let seg_size = self.header.seg_size as usize;
+        let n = self.header.index.len() as u64;
+        let batch = (workers * FN_WORKER_SEGMENTS) as u64;
+
+        self.from.seek(SeekFrom::Start(self.start))?;
+        let mut k0 = 0u64;
+        while k0 < n {
+            let kn = std::cmp::min(k0 + batch, n);
+            let size: usize = (k0..kn).map(|k| self.header.seg_len(k)).sum();
+
+            let mut data = vec![0u8; size];
+            self.from.read_exact(&mut data)?;
+
+            let groups: Vec<&[u8]> = data.chunks(seg_size * FN_WORKER_SEGMENTS).collect();
+            let (header, dn) = (&self.header, &self.dn);
+            let res = parallel(&groups, workers, |g, group| {
+                let first = k0 + (g * FN_WORKER_SEGMENTS) as u64;
+                group.chunks(seg_size).enumerate()
+                    .map(|(j, cipher)| header.decrypt(dn, first + j as u64, cipher))
+                    .collect::<Vec<_>>()
+            });
+
+            for plain in res.into_iter().flatten() {
+                let plain = plain.ok_or(Error::DecryptionFailed)?;
+                to.write_all(&plain)?;
+            }
+
+            k0 = kn;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for FnReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.header.len || buf.is_empty() {
+            return Ok(0)
+        }
+
+        let seg_size = u64::from(self.header.seg_size);
+        let (k, offset) = (self.pos / seg_size, (self.pos % seg_size) as usize);
+
+        let seg = self.segment(k)?;
+        let n = std::cmp::min(buf.len(), seg.len() - offset);
+        buf[..n].copy_from_slice(&seg[offset..offset + n]);
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for FnReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => checked_offset(self.header.len, d),
+            SeekFrom::Current(d) => checked_offset(self.pos, d)
+        };
+
+        self.pos = new.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid seek to a negative position!"))?;
+        Ok(self.pos)
+    }
+}
+
+// process the items with at most "workers" threads, the output order is preserved
+fn parallel<T: Sync, O: Send, F: Fn(usize, &T) -> O + Sync>(items: &[T], workers: usize, f: F) -> Vec<O> {
+    let per_worker = std::cmp::max(1, items.len().div_ceil(std::cmp::max(1, workers)));
+    let f = &f;
+
+    std::thread::scope(|sc| {
+        let handles: Vec<_> = items.chunks(per_worker).enumerate()
+            .map(|(w, part)| sc.spawn(move || {
+                part.iter().enumerate().map(|(j, item)| f(w * per_worker + j, item)).collect::<Vec<_>>()
+            }))
+            .collect();
+
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    })
+}
+
+fn read_full<R: Read>(from: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        let r = from.read(&mut buf[n..])?;
+        if r == 0 {
+            break
+        }
+
+        n += r;
+    }
+
+    Ok(n)
+}
+
+fn checked_offset(base: u64, d: i64) -> Option<u64> {
+    if d >= 0 {
+        base.checked_add(d as u64)
+    } else {
+        base.checked_sub(d.unsigned_abs())
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// FnContainer (read/write of complete files)
+//-----------------------------------------------------------------------------------------------------------
+pub struct FnContainer;
+impl FnContainer {
+    pub fn save<R: Read, W: Write + Seek>(keyp: &KeyPair, dn: &[u8; 16], len: u64, mut from: R, to: W) -> Result<W> {
+        let mut writer = FnWriter::new(keyp, dn, len, to)?;
+        std::io::copy(&mut from, &mut writer)?;
+        writer.finish()
+    }
+
+    pub fn load<R: Read + Seek, W: Write>(source: &RistrettoPoint, dn: &[u8; 16], from: R, mut to: W) -> Result<()> {
+        let mut reader = FnReader::open(source, dn, from)?;
+        std::io::copy(&mut reader, &mut to)?;
+        Ok(())
+    }
+
+    pub fn save_parallel<R: Read, W: Write + Seek>(keyp: &KeyPair, dn: &[u8; 16], len: u64, mut from: R, to: W, workers: usize) -> Result<W> {
+        if workers == 0 {
+            return Err(Error::InvalidParameter("Invalid number of workers!"))
+        }
+
+        let mut writer = FnWriter::new(keyp, dn, len, to)?;
+        let mut buf = vec![0u8; workers * FN_WORKER_SEGMENTS * FN_SEGMENT];
+        loop {
+            let n = read_full(&mut from, &mut buf)?;
+            if n == 0 {
+                break
+            }
+
+            writer.write_parallel(&buf[..n], workers)?;
+        }
+
+        writer.finish()
+    }
+
+    pub fn load_parallel<R: Read + Seek, W: Write>(source: &RistrettoPoint, dn: &[u8; 16], from: R, to: W, workers: usize) -> Result<()> {
+        if workers == 0 {
+            return Err(Error::InvalidParameter("Invalid number of workers!"))
+        }
+
+        let mut reader = FnReader::open(source, dn, from)?;
+        reader.read_parallel(to, workers)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn container_write_load() {
+        let dn = b"encryption123456";
+        let skp = KeyPair::new(); // source key-pair
+
+        let plaintext1 = sample(3 * FN_SEGMENT + 100);
+        let ciphertext = FnContainer::save(&skp, dn, plaintext1.len() as u64, plaintext1.as_slice(), Cursor::new(Vec::new())).unwrap();
+
+        let mut plaintext2 = Vec::new();
+        let ciphertext = ciphertext.into_inner();
+        FnContainer::load(&skp.key, dn, Cursor::new(ciphertext.clone()), &mut plaintext2).unwrap();
+        assert!(plaintext1 == plaintext2);
+
+        // signed by another source, nothing is released
+        let mut plaintext3 = Vec::new();
+        let res = FnContainer::load(&KeyPair::new().key, dn, Cursor::new(ciphertext), &mut plaintext3);
+        assert!(matches!(res, Err(Error::UnexpectedSigner)));
+        assert!(plaintext3.is_empty());
+    }
+
+    #[test]
+    fn container_parallel() {
+        let dn = b"encryption123456";
+        let skp = KeyPair::new(); // source key-pair
+
+        let plaintext1 = sample(20 * FN_SEGMENT + 100);
+        let len = plaintext1.len() as u64;
+
+        // compatible with the sequential reader and writer
+        let ciphertext = FnContainer::save_parallel(&skp, dn, len, plaintext1.as_slice(), Cursor::new(Vec::new()), 1).unwrap().into_inner();
+        let mut plaintext2 = Vec::new();
+        FnContainer::load(&skp.key, dn, Cursor::new(ciphertext), &mut plaintext2).unwrap();
+        assert!(plaintext1 == plaintext2);
+
+        let ciphertext = FnContainer::save(&skp, dn, len, plaintext1.as_slice(), Cursor::new(Vec::new())).unwrap().into_inner();
+        let mut plaintext2 = Vec::new();
+        FnContainer::load_parallel(&skp.key, dn, Cursor::new(ciphertext.clone()), &mut plaintext2, 3).unwrap();
+        assert!(plaintext1 == plaintext2);
+
+        let mut modified = ciphertext.clone();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        assert!(FnContainer::load_parallel(&skp.key, dn, Cursor::new(modified), Vec::new(), 4).is_err());
+
+        // no workers
+        let res = FnContainer::save_parallel(&skp, dn, len, plaintext1.as_slice(), Cursor::new(Vec::new()), 0);
+        assert!(matches!(res, Err(Error::InvalidParameter(_))));
+        assert!(matches!(FnContainer::load_parallel(&skp.key, dn, Cursor::new(ciphertext.clone()), Vec::new(), 0), Err(Error::InvalidParameter(_))));
+
+        // a length beyond the file is rejected before reading the index
+        let mut modified = ciphertext;
+        modified[25..33].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(FnContainer::load(&skp.key, dn, Cursor::new(modified), Vec::new()), Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn container_random_access() {
+        let dn = b"encryption123456";
+        let skp = KeyPair::new(); // source key-pair
+
+        let plaintext = sample(10_000);
+        let mut writer = FnWriter::with_segment(&skp, dn, plaintext.len() as u64, 1024, Cursor::new(Vec::new())).unwrap();
+        writer.write_all(&plaintext).unwrap();
+        let ciphertext = writer.finish().unwrap().into_inner();
+
+        let mut reader = FnReader::open(&skp.key, dn, Cursor::new(ciphertext)).unwrap();
+        assert!(reader.len() == 10_000);
+
+        let mut buf = vec![0u8; 3000];
+        reader.seek(SeekFrom::Start(5000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert!(buf[..] == plaintext[5000..8000]);
+
+        reader.seek(SeekFrom::End(-10)).unwrap();
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert!(tail[..] == plaintext[9990..]);
+    }
+
+    #[test]
+    fn container_integrity() {
+        let dn = b"encryption123456";
+        let skp = KeyPair::new(); // source key-pair
+
+        let plaintext = sample(5000);
+        let mut writer = FnWriter::with_segment(&skp, dn, plaintext.len() as u64, 1024, Cursor::new(Vec::new())).unwrap();
+        writer.write_all(&plaintext).unwrap();
+        let ciphertext = writer.finish().unwrap().into_inner();
+
+        // modified segment, only detected when reading it
+        let mut modified = ciphertext.clone();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        let mut reader = FnReader::open(&skp.key, dn, Cursor::new(modified)).unwrap();
+        assert!(reader.segment(0).is_ok());
+        assert!(matches!(reader.segment(4), Err(Error::DecryptionFailed)));
+
+        // modified index
+        let mut modified = ciphertext.clone();
+        modified[FN_FIXED_SIZE as usize + 8] ^= 1;
+        assert!(matches!(FnReader::open(&skp.key, dn, Cursor::new(modified)), Err(Error::InvalidSignature)));
+
+        // truncated file
+        let truncated = ciphertext[..ciphertext.len() - 10].to_vec();
+        let mut reader = FnReader::open(&skp.key, dn, Cursor::new(truncated)).unwrap();
+        assert!(std::io::copy(&mut reader, &mut Vec::new()).is_err());
+
+        // wrong length on write
+        let mut writer = FnWriter::new(&skp, dn, 10, Cursor::new(Vec::new())).unwrap();
+        writer.write_all(b"12345").unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use crate::crypto::{G, rnd_scalar};
+use crate::crypto::shares::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// Dealer (each curator deals a sub-polynomial of a secret it never reveals)
+//-----------------------------------------------------------------------------------------------------------
+pub struct Dealer {
+    pub i: u32,
+    poly: Polynomial
+}
+
+impl Dealer {
+    pub fn new(i: u32, t: usize) -> Self {
+        Self { i, poly: Polynomial::rnd(rnd_scalar(), t) }
+    }
+
+    pub fn deal(&self, n: usize) -> (Deal, ShareVector) {
+        let deal = Deal { dealer: self.i, commit: &self.poly * &G };
+        (deal, self.poly.shares(n))
+    }
+}
+
+// broadcasted to all parties, the private share is sent point-to-point
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Deal {
+    pub dealer: u32,
+    pub commit: RistrettoPolynomial
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// DkgParty (Feldman-style verification of the received shares)
+//-----------------------------------------------------------------------------------------------------------
+pub struct DkgParty {
+    pub i: u32,
+    pub t: usize,
+    deals: BTreeMap<u32, (RistrettoPolynomial, Share)>,
+    complaints: Vec<u32>
+}
+
+impl DkgParty {
+    pub fn new(i: u32, t: usize) -> Self {
+        Self { i, t, deals: BTreeMap::new(), complaints: Vec::new() }
+    }
+
+    pub fn complaints(&self) -> &[u32] {
+        &self.complaints
+    }
+
+    pub fn receive(&mut self, deal: &Deal, share: Share) -> bool {
+        let valid = share.i == self.i
+            && deal.commit.degree() == self.t
+            && deal.commit.verify(&(&share * &G));
+
+        if valid {
+            self.deals.insert(deal.dealer, (deal.commit.clone(), share));
+        } else {
+            self.complaints.push(deal.dealer);
+        }
+
+        valid
+    }
+
+    // "disqualified" is the union of all complaints broadcasted by the parties
+    pub fn finish(&self, disqualified: &[u32]) -> Option<DkgOutput> {
+        let qualified: Vec<_> = self.deals.iter()
+            .filter(|(dealer, _)| !disqualified.contains(dealer))
+            .map(|(_, deal)| deal)
+            .collect();
+
+        // at least one honest dealer must be in the qualified set
+        if qualified.len() <= self.t {
+            return None
+        }
+
+        let (commit, share) = qualified[0];
+        let mut commit = commit.clone();
+        let mut share = share.clone();
+        for (ck, sk) in qualified.iter().skip(1) {
+            commit = &commit + ck;
+            share = &share + sk;
+        }
+
+        Some(DkgOutput { share, commit })
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// DkgOutput
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DkgOutput {
+    pub share: Share,
+    pub commit: RistrettoPolynomial
+}
+
+impl DkgOutput {
+    pub fn key(&self) -> RistrettoPoint {
+        self.commit.A[0]
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// federation (all parties in one process, for tests, simulations and measurements)
+//-----------------------------------------------------------------------------------------------------------
+pub fn federation(t: usize, n: usize) -> Vec<DkgOutput> {
+    // every party deals a sub-polynomial, no one ever sees the master secret
+    let dealers: Vec<Dealer> = (1..=n).map(|i| Dealer::new(i as u32, t)).collect();
+    let deals: Vec<_> = dealers.iter().map(|d| d.deal(n)).collect();
+
+    let mut parties: Vec<DkgParty> = (1..=n).map(|i| DkgParty::new(i as u32, t)).collect();
+    for (deal, shares) in deals.iter() {
+        for (party, share) in parties.iter_mut().zip(shares.0.iter()) {
+            party.receive(deal, share.clone());
+        }
+    }
+
+    let mut disqualified: Vec<u32> = parties.iter().flat_map(|p| p.complaints().to_vec()).collect();
+    disqualified.sort();
+    disqualified.dedup();
+
+    parties.iter().map(|p| p.finish(&disqualified).unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(t: usize, n: usize, corrupt: Option<(usize, usize)>) -> Vec<Option<DkgOutput>> {
+        let dealers: Vec<Dealer> = (1..=n).map(|i| Dealer::new(i as u32, t)).collect();
+        let deals: Vec<_> = dealers.iter().map(|d| d.deal(n)).collect();
+
+        let mut parties: Vec<DkgParty> = (1..=n).map(|i| DkgParty::new(i as u32, t)).collect();
+        for (d, (deal, shares)) in deals.iter().enumerate() {
+            for (j, party) in parties.iter_mut().enumerate() {
+                let mut share = shares.0[j].clone();
+                if corrupt == Some((d, j)) {
+                    share = &share + &rnd_scalar();
+                }
+
+                party.receive(deal, share);
+            }
+        }
+
+        let mut disqualified: Vec<u32> = parties.iter().flat_map(|p| p.complaints().to_vec()).collect();
+        disqualified.sort();
+        disqualified.dedup();
+
+        parties.iter().map(|p| p.finish(&disqualified)).collect()
+    }
+
+    #[test]
+    fn test_dkg() {
+        let outputs: Vec<DkgOutput> = run(3, 7, None).into_iter().map(|o| o.unwrap()).collect();
+
+        let key = outputs[0].key();
+        assert!(outputs.iter().all(|o| o.key() == key && o.commit == outputs[0].commit));
+
+        let shares = ShareVector(outputs.iter().take(4).map(|o| o.share.clone()).collect());
+        assert!(shares.recover() * G == key);
+    }
+
+    #[test]
+    fn test_dkg_complaint() {
+        let outputs: Vec<DkgOutput> = run(2, 5, Some((1, 3))).into_iter().map(|o| o.unwrap()).collect();
+
+        // the dealer 2 is disqualified, but the remaining shares are still consistent
+        let key = outputs[0].key();
+        let shares = ShareVector(outputs.iter().skip(2).map(|o| o.share.clone()).collect());
+        assert!(shares.recover() * G == key);
+
+        for o in outputs.iter() {
+            assert!(o.commit.verify(&(&o.share * &G)));
+        }
+    }
+}
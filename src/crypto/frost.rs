@@ -0,0 +1,240 @@
+use std::fmt::{Debug, Formatter};
+
+use clear_on_drop::clear::Clear;
+
+use serde::{Serialize, Deserialize};
+
+use sha2::{Sha512, Digest};
+
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::traits::Identity;
+
+use crate::crypto::{G, rnd_scalar, KeyEncoder};
+use crate::crypto::shares::*;
+use crate::crypto::signatures::Signature;
+
+//-----------------------------------------------------------------------------------------------------------
+// Round 1: nonces and the broadcasted commitments
+//-----------------------------------------------------------------------------------------------------------
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NonceCommitment {
+    pub i: u32,
+    #[serde(with = "crate::crypto::b64")]
+    pub D: RistrettoPoint,
+    #[serde(with = "crate::crypto::b64")]
+    pub E: RistrettoPoint
+}
+
+impl Debug for NonceCommitment {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("NonceCommitment")
+            .field("i", &self.i)
+            .field("D", &self.D.encode())
+            .field("E", &self.E.encode())
+            .finish()
+    }
+}
+
+pub struct SigningNonces {
+    d: Scalar,
+    e: Scalar,
+    pub commitment: NonceCommitment
+}
+
+impl Drop for SigningNonces {
+    fn drop(&mut self) {
+        self.d.clear();
+        self.e.clear();
+    }
+}
+
+impl SigningNonces {
+    pub fn new(i: u32) -> Self {
+        let d = rnd_scalar();
+        let e = rnd_scalar();
+        Self { d, e, commitment: NonceCommitment { i, D: d * G, E: e * G } }
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Round 2: signature shares
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SignatureShare {
+    pub i: u32,
+    #[serde(with = "crate::crypto::b64")]
+    pub z: Scalar
+}
+
+impl Debug for SignatureShare {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("SignatureShare")
+            .field("i", &self.i)
+            .field("z", &self.z.encode())
+            .finish()
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// SigningPackage (the commitments of the selected signers and the message)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SigningPackage {
+    #[serde(with = "crate::crypto::b64")]
+    pub key: RistrettoPoint,
+    pub dhash: Vec<u8>,
+    pub commitments: Vec<NonceCommitment>
+}
+
+impl SigningPackage {
+    pub fn new(key: RistrettoPoint, dhash: &[u8], mut commitments: Vec<NonceCommitment>) -> Self {
+        commitments.sort_by_key(|c| c.i);
+        Self { key, dhash: dhash.into(), commitments }
+    }
+
+    // a signer only responds if its nonces are in the package, the nonces are consumed
+    pub fn sign(&self, share: &Share, nonces: SigningNonces) -> Option<SignatureShare> {
+        let cm = self.commitments.iter().find(|c| c.i == share.i)?;
+        if cm.D != nonces.commitment.D || cm.E != nonces.commitment.E || !self.is_valid() {
+            return None
+        }
+
+        let c = self.challenge();
+        let z = nonces.d + nonces.e * self.binding(share.i) - self.lagrange(share.i) * share.yi * c;
+        Some(SignatureShare { i: share.i, z })
+    }
+
+    #[allow(non_snake_case)]
+    pub fn aggregate(&self, commit: &RistrettoPolynomial, shares: &[SignatureShare]) -> Recovered<Signature> {
+        if !self.is_valid() || self.commitments.len() <= commit.degree() {
+            return Recovered { value: None, faulty: Vec::new() }
+        }
+
+        let c = self.challenge();
+        let mut p = Scalar::zero();
+        let mut faulty = Vec::<u32>::new();
+        for cm in self.commitments.iter() {
+            // a missing or incorrect signature share identifies the misbehaving signer
+            let x = Scalar::from(u64::from(cm.i));
+            let Yi = commit.evaluate(&x);
+            let expected = cm.D + self.binding(cm.i) * cm.E - c * self.lagrange(cm.i) * Yi;
+
+            match shares.iter().find(|s| s.i == cm.i) {
+                Some(share) if share.z * G == expected => p += share.z,
+                _ => faulty.push(cm.i)
+            }
+        }
+
+        let value = if faulty.is_empty() { Some(Signature { c, p }) } else { None };
+        Recovered { value, faulty }
+    }
+
+    fn is_valid(&self) -> bool {
+        let mut indexes: Vec<u32> = self.commitments.iter().map(|c| c.i).collect();
+        indexes.sort();
+        indexes.dedup();
+
+        indexes.len() == self.commitments.len()
+            && self.commitments.iter().all(|c| c.D != RistrettoPoint::identity() && c.E != RistrettoPoint::identity())
+    }
+
+    fn binding(&self, i: u32) -> Scalar {
+        let mut hasher = Sha512::new()
+            .chain(i.to_le_bytes())
+            .chain(self.key.compress().as_bytes())
+            .chain(&self.dhash);
+
+        for cm in self.commitments.iter() {
+            hasher = hasher
+                .chain(cm.i.to_le_bytes())
+                .chain(cm.D.compress().as_bytes())
+                .chain(cm.E.compress().as_bytes());
+        }
+
+        Scalar::from_hash(hasher)
+    }
+
+    #[allow(non_snake_case)]
+    fn challenge(&self) -> Scalar {
+        let mut M = RistrettoPoint::identity();
+        for cm in self.commitments.iter() {
+            M += cm.D + self.binding(cm.i) * cm.E;
+        }
+
+        // the same challenge of Signature::verify
+        let hasher = Sha512::new()
+            .chain(self.key.compress().as_bytes())
+            .chain(M.compress().as_bytes())
+            .chain(&self.dhash);
+
+        Scalar::from_hash(hasher)
+    }
+
+    fn lagrange(&self, i: u32) -> Scalar {
+        let range = self.commitments.iter().map(|c| Scalar::from(c.i)).collect::<Vec<_>>();
+        let k = self.commitments.iter().position(|c| c.i == i).unwrap();
+        Polynomial::l_i(&range, k)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    fn setup(t: usize, n: usize) -> (KeyPair, RistrettoPolynomial, ShareVector) {
+        let ekp = KeyPair::new();
+        let poly = Polynomial::rnd(ekp.s, t);
+        let commit = &poly * &G;
+        let shares = poly.shares(n);
+
+        (ekp, commit, shares)
+    }
+
+    #[test]
+    fn test_threshold_sign() {
+        let (ekp, commit, shares) = setup(2, 5);
+        let dhash = Sha512::new().chain("break-the-glass attestation").result();
+
+        let signers: Vec<&Share> = vec![&shares.0[0], &shares.0[2], &shares.0[4]];
+        let nonces: Vec<SigningNonces> = signers.iter().map(|s| SigningNonces::new(s.i)).collect();
+
+        let pkg = SigningPackage::new(ekp.key, &dhash, nonces.iter().map(|n| n.commitment.clone()).collect());
+        let sig_shares: Vec<SignatureShare> = signers.iter().zip(nonces)
+            .map(|(s, n)| pkg.sign(s, n).unwrap())
+            .collect();
+
+        let res = pkg.aggregate(&commit, &sig_shares);
+        assert!(res.faulty.is_empty());
+        assert!(res.value.unwrap().verify(&ekp.key, &dhash));
+    }
+
+    #[test]
+    fn test_threshold_sign_misbehave() {
+        let (ekp, commit, shares) = setup(1, 3);
+        let dhash = Sha512::new().chain("break-the-glass attestation").result();
+
+        let n1 = SigningNonces::new(1);
+        let n3 = SigningNonces::new(3);
+        let pkg = SigningPackage::new(ekp.key, &dhash, vec![n1.commitment.clone(), n3.commitment.clone()]);
+
+        let s1 = pkg.sign(&shares.0[0], n1).unwrap();
+        let mut s3 = pkg.sign(&shares.0[2], n3).unwrap();
+        s3.z += Scalar::one();
+
+        let res = pkg.aggregate(&commit, &[s1.clone(), s3]);
+        assert!(res.value.is_none() && res.faulty == vec![3]);
+
+        // a missing signature share
+        let res = pkg.aggregate(&commit, &[s1]);
+        assert!(res.value.is_none() && res.faulty == vec![3]);
+
+        // not enough signers for the threshold
+        let n2 = SigningNonces::new(2);
+        let pkg = SigningPackage::new(ekp.key, &dhash, vec![n2.commitment.clone()]);
+        assert!(pkg.aggregate(&commit, &[pkg.sign(&shares.0[1], n2).unwrap()]).value.is_none());
+    }
+}
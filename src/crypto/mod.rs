@@ -6,6 +6,13 @@ use curve25519_dalek::scalar::Scalar;
 use curve25519_dalek::ristretto::{RistrettoPoint, CompressedRistretto};
 use curve25519_dalek::constants::{RISTRETTO_BASEPOINT_POINT};
 
+use crate::errors::{Error, Result};
+
+pub mod dkg;
+pub mod frost;
+pub mod proofs;
+pub mod refresh;
+pub mod reshare;
 pub mod shares;
 pub mod signatures;
 
@@ -28,13 +35,19 @@ impl KeyPair {
     }
 }
 
+impl Default for KeyPair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 pub trait KeyEncoder {
     fn encode(&self) -> String;
 }
 
-pub trait HardKeyDecoder<T> {
-    fn decode(&self) -> T;
+pub trait KeyDecoder<T> {
+    fn decode(&self) -> Result<T>;
 }
 
 impl KeyEncoder for CompressedRistretto {
@@ -55,27 +68,123 @@ impl KeyEncoder for Scalar {
     }
 }
 
-impl HardKeyDecoder<CompressedRistretto> for String {
-    fn decode(&self) -> CompressedRistretto {
-        let data = base64::decode(self.as_str()).expect("Unable to decode base58 input!");
-        CompressedRistretto::from_slice(&data)
+impl KeyDecoder<CompressedRistretto> for String {
+    fn decode(&self) -> Result<CompressedRistretto> {
+        let data = base64::decode(self.as_str()).map_err(|_| Error::MalformedKey)?;
+        if data.len() != 32 {
+            return Err(Error::MalformedKey)
+        }
+
+        Ok(CompressedRistretto::from_slice(&data))
     }
 }
 
-impl HardKeyDecoder<RistrettoPoint> for String {
-    fn decode(&self) -> RistrettoPoint {
-        let data = base64::decode(self.as_str()).expect("Unable to decode base58 input!");
-        let point = CompressedRistretto::from_slice(&data);
-        point.decompress().expect("Unable to decompress RistrettoPoint!")
+impl KeyDecoder<RistrettoPoint> for String {
+    fn decode(&self) -> Result<RistrettoPoint> {
+        let point: CompressedRistretto = self.decode()?;
+        point.decompress().ok_or(Error::MalformedKey)
     }
 }
 
-impl HardKeyDecoder<Scalar> for String {
-    fn decode(&self) -> Scalar {
-        let data = base64::decode(self.as_str()).expect("Unable to decode base58 input!");
+impl KeyDecoder<Scalar> for String {
+    fn decode(&self) -> Result<Scalar> {
+        let data = base64::decode(self.as_str()).map_err(|_| Error::MalformedKey)?;
+        if data.len() != 32 {
+            return Err(Error::MalformedKey)
+        }
+
         let mut bytes: [u8; 32] = Default::default();
-        bytes.copy_from_slice(&data[0..32]);
+        bytes.copy_from_slice(&data);
+
+        Scalar::from_canonical_bytes(bytes).ok_or(Error::MalformedKey)
+    }
+}
+
+
+//-----------------------------------------------------------------------------------------------------------
+// Serde "with" modules, keys are base64 strings in human readable formats (JSON) and raw bytes otherwise
+//-----------------------------------------------------------------------------------------------------------
+pub mod b64 {
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+    use serde::de::Error;
+    use super::{KeyEncoder, KeyDecoder};
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error> where T: KeyEncoder + Serialize, S: Serializer {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&value.encode())
+        } else {
+            value.serialize(serializer)
+        }
+    }
 
-        Scalar::from_canonical_bytes(bytes).expect("Unable to decode Scalar!")
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error> where T: Deserialize<'de>, String: KeyDecoder<T>, D: Deserializer<'de> {
+        if deserializer.is_human_readable() {
+            let data = String::deserialize(deserializer)?;
+            data.decode().map_err(|_| D::Error::custom("Invalid base64 key!"))
+        } else {
+            T::deserialize(deserializer)
+        }
     }
-}
\ No newline at end of file
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> where T: KeyEncoder + Serialize, S: Serializer {
+            if serializer.is_human_readable() {
+                value.as_ref().map(|v| v.encode()).serialize(serializer)
+            } else {
+                value.serialize(serializer)
+            }
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error> where T: Deserialize<'de>, String: KeyDecoder<T>, D: Deserializer<'de> {
+            if deserializer.is_human_readable() {
+                let data = Option::<String>::deserialize(deserializer)?;
+                data.map(|d| d.decode().map_err(|_| D::Error::custom("Invalid base64 key!"))).transpose()
+            } else {
+                Option::<T>::deserialize(deserializer)
+            }
+        }
+    }
+
+    pub mod vec {
+        use super::*;
+
+        pub fn serialize<T, S>(value: &[T], serializer: S) -> Result<S::Ok, S::Error> where T: KeyEncoder + Serialize, S: Serializer {
+            if serializer.is_human_readable() {
+                value.iter().map(|v| v.encode()).collect::<Vec<_>>().serialize(serializer)
+            } else {
+                value.serialize(serializer)
+            }
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error> where T: Deserialize<'de>, String: KeyDecoder<T>, D: Deserializer<'de> {
+            if deserializer.is_human_readable() {
+                let data = Vec::<String>::deserialize(deserializer)?;
+                data.iter().map(|d| d.decode().map_err(|_| D::Error::custom("Invalid base64 key!"))).collect()
+            } else {
+                Vec::<T>::deserialize(deserializer)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_decode() {
+        let kp = KeyPair::new();
+
+        let key: RistrettoPoint = kp.key.encode().decode().unwrap();
+        let s: Scalar = kp.s.encode().decode().unwrap();
+        assert!(key == kp.key && s == kp.s);
+
+        let res: Result<RistrettoPoint> = "not-base64!".to_string().decode();
+        assert!(matches!(res, Err(Error::MalformedKey)));
+
+        let res: Result<Scalar> = base64::encode(&[0xffu8; 32]).decode();
+        assert!(matches!(res, Err(Error::MalformedKey)));
+    }
+}
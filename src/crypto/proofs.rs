@@ -0,0 +1,98 @@
+use std::fmt::{Debug, Formatter};
+
+use serde::{Serialize, Deserialize};
+
+use sha2::{Sha512, Digest};
+
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use crate::crypto::{G, KeyEncoder};
+
+//-----------------------------------------------------------------------------------------------------------
+// Chaum-Pedersen proof of discrete logarithm equality, log_G(P1) == log_B(P2)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DleqProof {
+    #[serde(with = "crate::crypto::b64")]
+    pub c: Scalar,
+    #[serde(with = "crate::crypto::b64")]
+    pub p: Scalar
+}
+
+impl Debug for DleqProof {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("DleqProof")
+            .field("c", &self.c.encode())
+            .field("p", &self.p.encode())
+            .finish()
+    }
+}
+
+impl DleqProof {
+    #[allow(non_snake_case)]
+    pub fn prove(s: &Scalar, B: &RistrettoPoint, P1: &RistrettoPoint, P2: &RistrettoPoint) -> Self {
+        let hasher = Sha512::new()
+            .chain(s.as_bytes())
+            .chain(B.compress().as_bytes())
+            .chain(P2.compress().as_bytes());
+
+        let m = Scalar::from_hash(hasher);
+        let M1 = m * G;
+        let M2 = m * B;
+
+        let c = Self::challenge(B, P1, P2, &M1, &M2);
+        let p = m - c * s;
+
+        Self { c, p }
+    }
+
+    #[allow(non_snake_case)]
+    pub fn verify(&self, B: &RistrettoPoint, P1: &RistrettoPoint, P2: &RistrettoPoint) -> bool {
+        let M1 = self.c * P1 + self.p * G;
+        let M2 = self.c * P2 + self.p * B;
+
+        let c = Self::challenge(B, P1, P2, &M1, &M2);
+        c == self.c
+    }
+
+    #[allow(non_snake_case)]
+    fn challenge(B: &RistrettoPoint, P1: &RistrettoPoint, P2: &RistrettoPoint, M1: &RistrettoPoint, M2: &RistrettoPoint) -> Scalar {
+        let hasher = Sha512::new()
+            .chain(B.compress().as_bytes())
+            .chain(P1.compress().as_bytes())
+            .chain(P2.compress().as_bytes())
+            .chain(M1.compress().as_bytes())
+            .chain(M2.compress().as_bytes());
+
+        Scalar::from_hash(hasher)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::rnd_scalar;
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_correct() {
+        let s = rnd_scalar();
+        let B = rnd_scalar() * G;
+
+        let proof = DleqProof::prove(&s, &B, &(s * G), &(s * B));
+        assert!(proof.verify(&B, &(s * G), &(s * B)));
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_incorrect() {
+        let s = rnd_scalar();
+        let r = rnd_scalar();
+        let B = rnd_scalar() * G;
+
+        let proof = DleqProof::prove(&s, &B, &(s * G), &(r * B));
+        assert!(!proof.verify(&B, &(s * G), &(r * B)));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+
+use crate::crypto::G;
+use crate::crypto::dkg::DkgOutput;
+use crate::crypto::shares::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// RefreshDeal (shares of a random zero-constant polynomial for a refresh epoch)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RefreshDeal {
+    pub epoch: u64,
+    pub dealer: u32,
+    pub commit: RistrettoPolynomial
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// RefreshParty (proactive refresh, the master key and the existing records remain valid)
+//-----------------------------------------------------------------------------------------------------------
+pub struct RefreshParty {
+    pub epoch: u64,
+    state: DkgOutput,
+    deals: BTreeMap<u32, (RistrettoPolynomial, Share)>,
+    complaints: Vec<u32>
+}
+
+impl RefreshParty {
+    pub fn new(epoch: u64, state: DkgOutput) -> Self {
+        Self { epoch, state, deals: BTreeMap::new(), complaints: Vec::new() }
+    }
+
+    pub fn complaints(&self) -> &[u32] {
+        &self.complaints
+    }
+
+    pub fn deal(&self, n: usize) -> (RefreshDeal, ShareVector) {
+        let poly = Polynomial::rnd(Scalar::zero(), self.state.commit.degree());
+        let deal = RefreshDeal { epoch: self.epoch, dealer: self.state.share.i, commit: &poly * &G };
+        (deal, poly.shares(n))
+    }
+
+    pub fn receive(&mut self, deal: &RefreshDeal, share: Share) -> bool {
+        let valid = deal.epoch == self.epoch
+            && share.i == self.state.share.i
+            && deal.commit.degree() == self.state.commit.degree()
+            && deal.commit.A[0] == RistrettoPoint::identity()
+            && deal.commit.verify(&(&share * &G));
+
+        if valid {
+            self.deals.insert(deal.dealer, (deal.commit.clone(), share));
+        } else {
+            self.complaints.push(deal.dealer);
+        }
+
+        valid
+    }
+
+    // "disqualified" is the union of all complaints broadcasted by the parties
+    pub fn finish(&self, disqualified: &[u32]) -> Option<DkgOutput> {
+        let qualified: Vec<_> = self.deals.iter()
+            .filter(|(dealer, _)| !disqualified.contains(dealer))
+            .map(|(_, deal)| deal)
+            .collect();
+
+        // at least one honest dealer must be in the qualified set
+        if qualified.len() <= self.state.commit.degree() {
+            return None
+        }
+
+        let mut commit = self.state.commit.clone();
+        let mut share = self.state.share.clone();
+        for (ck, sk) in qualified.iter() {
+            commit = &commit + ck;
+            share = &share + sk;
+        }
+
+        if commit.A[0] != self.state.key() || !commit.verify(&(&share * &G)) {
+            return None
+        }
+
+        Some(DkgOutput { share, commit })
+    }
+}
+
+// refreshed public shares (yi*G) must still interpolate to the master key
+pub fn check(key: &RistrettoPoint, public: &RistrettoShareVector) -> bool {
+    public.recover() == *key
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    #[test]
+    fn test_refresh() {
+        let t = 2;
+        let n = 5;
+
+        let ekp = KeyPair::new();
+        let poly = Polynomial::rnd(ekp.s, t);
+        let commit = &poly * &G;
+        let old = poly.shares(n);
+
+        let mut parties: Vec<RefreshParty> = old.0.iter()
+            .map(|s| RefreshParty::new(1, DkgOutput { share: s.clone(), commit: commit.clone() }))
+            .collect();
+
+        let deals: Vec<_> = parties.iter().map(|p| p.deal(n)).collect();
+        for (deal, shares) in deals.iter() {
+            for (party, share) in parties.iter_mut().zip(shares.0.iter()) {
+                assert!(party.receive(deal, share.clone()));
+            }
+        }
+
+        let outputs: Vec<DkgOutput> = parties.iter().map(|p| p.finish(&[]).unwrap()).collect();
+        let new = ShareVector(outputs.iter().map(|o| o.share.clone()).collect());
+        assert!(outputs.iter().all(|o| o.key() == ekp.key));
+        assert!(check(&ekp.key, &(&new * &G)));
+        assert!(new.recover() == ekp.s);
+
+        // mixing old and new shares is useless
+        let mixed = ShareVector(vec![old.0[0].clone(), old.0[1].clone(), new.0[2].clone()]);
+        assert!(mixed.recover() != ekp.s);
+    }
+
+    #[test]
+    fn test_refresh_invalid_deal() {
+        let poly = Polynomial::rnd(KeyPair::new().s, 1);
+        let commit = &poly * &G;
+        let shares = poly.shares(3);
+
+        let mut party = RefreshParty::new(2, DkgOutput { share: shares.0[0].clone(), commit: commit.clone() });
+
+        // a deal that is not zero-constant would change the master key
+        let bad = Polynomial::rnd(Scalar::one(), 1);
+        let deal = RefreshDeal { epoch: 2, dealer: 2, commit: &bad * &G };
+        assert!(!party.receive(&deal, bad.shares(3).0[0].clone()));
+
+        // a deal from another epoch
+        let other = RefreshParty::new(1, DkgOutput { share: shares.0[1].clone(), commit });
+        let (deal, dshares) = other.deal(3);
+        assert!(!party.receive(&deal, dshares.0[0].clone()));
+        assert!(party.complaints() == [2, 2]);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::scalar::Scalar;
+
+use crate::crypto::G;
+use crate::crypto::dkg::DkgOutput;
+use crate::crypto::shares::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// ReshareDeal (an old holder deals its own share to the new committee)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReshareDeal {
+    pub dealer: u32,
+    pub commit: RistrettoPolynomial
+}
+
+impl DkgOutput {
+    pub fn reshare(&self, t: usize, n: usize) -> (ReshareDeal, ShareVector) {
+        let poly = Polynomial::rnd(self.share.yi, t);
+        let deal = ReshareDeal { dealer: self.share.i, commit: &poly * &G };
+        (deal, poly.shares(n))
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// ReshareParty (a holder of the new (t, n) committee)
+//-----------------------------------------------------------------------------------------------------------
+pub struct ReshareParty {
+    pub i: u32,
+    pub t: usize,
+    old: RistrettoPolynomial,
+    deals: BTreeMap<u32, (RistrettoPolynomial, Share)>,
+    complaints: Vec<u32>
+}
+
+impl ReshareParty {
+    pub fn new(i: u32, t: usize, old: RistrettoPolynomial) -> Self {
+        Self { i, t, old, deals: BTreeMap::new(), complaints: Vec::new() }
+    }
+
+    pub fn complaints(&self) -> &[u32] {
+        &self.complaints
+    }
+
+    pub fn receive(&mut self, deal: &ReshareDeal, share: Share) -> bool {
+        // the dealt secret must be the share of the old holder, checked against the old commitment
+        let x = Scalar::from(u64::from(deal.dealer));
+        let valid = share.i == self.i
+            && deal.commit.degree() == self.t
+            && deal.commit.A[0] == self.old.evaluate(&x)
+            && deal.commit.verify(&(&share * &G));
+
+        if valid {
+            self.deals.insert(deal.dealer, (deal.commit.clone(), share));
+        } else {
+            self.complaints.push(deal.dealer);
+        }
+
+        valid
+    }
+
+    // "disqualified" is the union of all complaints broadcasted by the parties
+    pub fn finish(&self, disqualified: &[u32]) -> Option<DkgOutput> {
+        // the same qualified subset of old holders is selected by all the new holders
+        let qualified: Vec<_> = self.deals.iter()
+            .filter(|(dealer, _)| !disqualified.contains(dealer))
+            .take(self.old.degree() + 1)
+            .collect();
+
+        if qualified.len() <= self.old.degree() {
+            return None
+        }
+
+        let range = qualified.iter().map(|(dealer, _)| Scalar::from(**dealer)).collect::<Vec<_>>();
+
+        let mut commit: Option<RistrettoPolynomial> = None;
+        let mut share: Option<Share> = None;
+        for (k, (_, (ck, sk))) in qualified.iter().enumerate() {
+            let l = Polynomial::l_i(&range, k);
+            let lck = ck * &l;
+            let lsk = sk * &l;
+
+            commit = Some(match commit { None => lck, Some(acc) => &acc + &lck });
+            share = Some(match share { None => lsk, Some(acc) => &acc + &lsk });
+        }
+
+        let (commit, share) = (commit?, share?);
+        if commit.A[0] != self.old.A[0] {
+            return None
+        }
+
+        Some(DkgOutput { share, commit })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::structs::*;
+
+    #[test]
+    fn test_reshare() {
+        let ekp = KeyPair::new();
+        let skp = KeyPair::new();
+
+        // record encrypted under the (1, 3) committee
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd.clone());
+        let chain = RnChain::new(r).unwrap();
+
+        let poly = Polynomial::rnd(ekp.s, 1);
+        let commit = &poly * &G;
+        let old: Vec<DkgOutput> = poly.shares(3).0.iter()
+            .map(|s| DkgOutput { share: s.clone(), commit: commit.clone() })
+            .collect();
+
+        // move to a (2, 5) committee, old holder 1 is not available
+        let (t, n) = (2, 5);
+        let mut parties: Vec<ReshareParty> = (1..=n).map(|i| ReshareParty::new(i as u32, t, commit.clone())).collect();
+        for holder in old.iter().skip(1) {
+            let (deal, shares) = holder.reshare(t, n);
+            for (party, share) in parties.iter_mut().zip(shares.0.iter()) {
+                assert!(party.receive(&deal, share.clone()));
+            }
+        }
+
+        let outputs: Vec<DkgOutput> = parties.iter().map(|p| p.finish(&[]).unwrap()).collect();
+        assert!(outputs.iter().all(|o| o.key() == ekp.key && o.commit.degree() == t));
+
+        let ei = ShareVector(outputs.iter().skip(2).map(|o| o.share.clone()).collect());
+        let alpha = (&ei * chain.kn()).recover().compress();
+        let refs = chain.recover(&alpha).unwrap();
+        assert!(refs[0] == rd.file);
+    }
+
+    #[test]
+    fn test_reshare_invalid_deal() {
+        let poly = Polynomial::rnd(KeyPair::new().s, 1);
+        let commit = &poly * &G;
+        let shares = poly.shares(3);
+
+        // the old holder 2 deals a different secret
+        let cheat = DkgOutput { share: shares.0[0].clone(), commit: commit.clone() };
+        let (mut deal, dshares) = cheat.reshare(1, 2);
+        deal.dealer = 2;
+
+        let mut party = ReshareParty::new(1, 1, commit);
+        assert!(!party.receive(&deal, dshares.0[0].clone()));
+        assert!(party.complaints() == [2]);
+        assert!(party.finish(&[]).is_none());
+    }
+}
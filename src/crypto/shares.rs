@@ -8,7 +8,9 @@ use serde::{Serialize, Deserialize};
 use curve25519_dalek::ristretto::RistrettoPoint;
 use curve25519_dalek::scalar::Scalar;
 
-use crate::crypto::{rnd_scalar, KeyEncoder};
+use crate::crypto::{G, rnd_scalar, KeyEncoder};
+use crate::crypto::proofs::DleqProof;
+use crate::errors::{Error, Result};
 
 //-----------------------------------------------------------------------------------------------------------
 // Scalar Share
@@ -16,6 +18,7 @@ use crate::crypto::{rnd_scalar, KeyEncoder};
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Share {
     pub i: u32,
+    #[serde(with = "crate::crypto::b64")]
     pub yi: Scalar
 }
 
@@ -34,7 +37,7 @@ impl Debug for Share {
     }
 }
 
-impl<'a, 'b> Add<&'b Share> for &'a Share {
+impl<'b> Add<&'b Share> for &Share {
     type Output = Share;
     fn add(self, rhs: &'b Share) -> Share {
         assert!(self.i == rhs.i);
@@ -42,14 +45,14 @@ impl<'a, 'b> Add<&'b Share> for &'a Share {
     }
 }
 
-impl<'a, 'b> Add<&'b Scalar> for &'a Share {
+impl<'b> Add<&'b Scalar> for &Share {
     type Output = Share;
     fn add(self, rhs: &'b Scalar) -> Share {
         Share { i: self.i, yi: self.yi + rhs }
     }
 }
 
-impl<'a, 'b> Sub<&'b Share> for &'a Share {
+impl<'b> Sub<&'b Share> for &Share {
     type Output = Share;
     fn sub(self, rhs: &'b Share) -> Share {
         assert!(self.i == rhs.i);
@@ -57,27 +60,35 @@ impl<'a, 'b> Sub<&'b Share> for &'a Share {
     }
 }
 
-impl<'a, 'b> Sub<&'b Scalar> for &'a Share {
+impl<'b> Sub<&'b Scalar> for &Share {
     type Output = Share;
     fn sub(self, rhs: &'b Scalar) -> Share {
         Share { i: self.i, yi: self.yi - rhs }
     }
 }
 
-impl<'a, 'b> Mul<&'b Scalar> for &'a Share {
+impl<'b> Mul<&'b Scalar> for &Share {
     type Output = Share;
     fn mul(self, rhs: &'b Scalar) -> Share {
         Share { i: self.i, yi: self.yi * rhs }
     }
 }
 
-impl<'a, 'b> Mul<&'b RistrettoPoint> for &'a Share {
+impl<'b> Mul<&'b RistrettoPoint> for &Share {
     type Output = RistrettoShare;
     fn mul(self, rhs: &'b RistrettoPoint) -> RistrettoShare {
         RistrettoShare { i: self.i, Yi: self.yi * rhs }
     }
 }
 
+impl Share {
+    pub fn prove(&self, base: &RistrettoPoint) -> ProvedRistrettoShare {
+        let share = self * base;
+        let proof = DleqProof::prove(&self.yi, base, &(self.yi * G), &share.Yi);
+        ProvedRistrettoShare { share, proof }
+    }
+}
+
 //-----------------------------------------------------------------------------------------------------------
 // ShareVector
 //-----------------------------------------------------------------------------------------------------------
@@ -95,6 +106,59 @@ impl ShareVector {
 
         acc
     }
+
+    // Berlekamp-Welch decoding, corrects up to (len - t - 1)/2 faulty shares
+    pub fn recover_robust(&self, t: usize) -> Recovered<Scalar> {
+        let mut faulty = Vec::<u32>::new();
+        if self.0.len() <= t {
+            return Recovered { value: None, faulty }
+        }
+
+        let xs = self.0.iter().map(|s| Scalar::from(s.i)).collect::<Vec<_>>();
+        let ys = self.0.iter().map(|s| s.yi).collect::<Vec<_>>();
+
+        let e = (self.0.len() - t - 1) / 2;
+        let value = berlekamp_welch(&xs, &ys, t, e).and_then(|poly| {
+            for (k, item) in self.0.iter().enumerate() {
+                if poly.evaluate(&xs[k]) != item.yi {
+                    faulty.push(item.i);
+                }
+            }
+
+            if faulty.len() <= e { Some(poly.a[0]) } else { None }
+        });
+
+        Recovered { value, faulty }
+    }
+
+    // consistency checks against the Feldman commitment, corrects up to (len - t - 1) faulty shares
+    pub fn recover_checked(&self, commit: &RistrettoPolynomial) -> Recovered<Scalar> {
+        let mut valid = Vec::<Share>::new();
+        let mut faulty = Vec::<u32>::new();
+        for item in self.0.iter() {
+            if valid.iter().any(|s| s.i == item.i) {
+                continue
+            }
+
+            if commit.verify(&(item * &G)) {
+                valid.push(item.clone());
+            } else {
+                faulty.push(item.i);
+            }
+        }
+
+        let value = if valid.len() > commit.degree() {
+            Some(ShareVector(valid).recover())
+        } else {
+            None
+        };
+
+        Recovered { value, faulty }
+    }
+
+    pub fn prove(&self, base: &RistrettoPoint) -> ProvedRistrettoShareVector {
+        ProvedRistrettoShareVector(self.0.iter().map(|s| s.prove(base)).collect())
+    }
 }
 
 impl Drop for ShareVector {
@@ -105,7 +169,7 @@ impl Drop for ShareVector {
     }
 }
 
-impl<'a, 'b> Mul<&'b RistrettoPoint> for &'a ShareVector {
+impl<'b> Mul<&'b RistrettoPoint> for &ShareVector {
     type Output = RistrettoShareVector;
     fn mul(self, rhs: &'b RistrettoPoint) -> RistrettoShareVector {
         let res: Vec<RistrettoShare> = self.0.iter().map(|s| s * rhs).collect();
@@ -116,6 +180,8 @@ impl<'a, 'b> Mul<&'b RistrettoPoint> for &'a ShareVector {
 //-----------------------------------------------------------------------------------------------------------
 // RistrettoShareVector
 //-----------------------------------------------------------------------------------------------------------
+pub const ROBUST_MAX_SHARES: usize = 16; // C(16, 7) consistency checks in the worst case
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RistrettoShareVector(pub Vec<RistrettoShare>);
 
@@ -130,6 +196,53 @@ impl RistrettoShareVector {
 
         acc
     }
+
+    // search for the smallest set of shares to drop so that the remaining ones lie on a polynomial of degree t.
+    // Corrects up to (len - t - 1)/2 faulty shares, the cost grows with C(len, faulty) so it's only for the
+    // cases without a commitment and at most ROBUST_MAX_SHARES shares (no value for more).
+    // NOTE: with a commitment use ProvedRistrettoShareVector::recover, the DLEQ proofs check each share in O(n).
+    pub fn recover_robust(&self, t: usize) -> Recovered<RistrettoPoint> {
+        let n = self.0.len();
+        if n <= t || n > ROBUST_MAX_SHARES {
+            return Recovered { value: None, faulty: Vec::new() }
+        }
+
+        let range = self.0.iter().map(|s| Scalar::from(s.i)).collect::<Vec<_>>();
+        let e = (n - t - 1) / 2;
+        for k in 0..=e {
+            let mut drop: Vec<usize> = (0..k).collect();
+            loop {
+                let keep: Vec<usize> = (0..n).filter(|j| !drop.contains(j)).collect();
+                if self.consistent(&range, &keep, t) {
+                    let shares = keep.iter().take(t + 1).map(|j| self.0[*j].clone()).collect();
+                    let faulty = drop.iter().map(|j| self.0[*j].i).collect();
+                    return Recovered { value: Some(RistrettoShareVector(shares).recover()), faulty }
+                }
+
+                if !next_combination(&mut drop, n) {
+                    break
+                }
+            }
+        }
+
+        Recovered { value: None, faulty: Vec::new() }
+    }
+
+    fn consistent(&self, range: &[Scalar], keep: &[usize], t: usize) -> bool {
+        let base: Vec<Scalar> = keep.iter().take(t + 1).map(|j| range[*j]).collect();
+        for j in keep.iter().skip(t + 1) {
+            let mut acc = RistrettoPoint::default();
+            for (i, b) in keep.iter().take(t + 1).enumerate() {
+                acc += lx_at(&base, i, &range[*j]) * self.0[*b].Yi;
+            }
+
+            if acc != self.0[*j].Yi {
+                return false
+            }
+        }
+
+        true
+    }
 }
 
 //-----------------------------------------------------------------------------------------------------------
@@ -139,6 +252,7 @@ impl RistrettoShareVector {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RistrettoShare {
     pub i: u32,
+    #[serde(with = "crate::crypto::b64")]
     pub Yi: RistrettoPoint
 }
 
@@ -151,27 +265,87 @@ impl Debug for RistrettoShare {
     }
 }
 
-impl<'a, 'b> Add<&'b RistrettoPoint> for &'a RistrettoShare {
+impl<'b> Add<&'b RistrettoPoint> for &RistrettoShare {
     type Output = RistrettoShare;
     fn add(self, rhs: &'b RistrettoPoint) -> RistrettoShare {
         RistrettoShare { i: self.i, Yi: self.Yi + rhs }
     }
 }
 
-impl<'a, 'b> Sub<&'b RistrettoPoint> for &'a RistrettoShare {
+impl<'b> Sub<&'b RistrettoPoint> for &RistrettoShare {
     type Output = RistrettoShare;
     fn sub(self, rhs: &'b RistrettoPoint) -> RistrettoShare {
         RistrettoShare { i: self.i, Yi: self.Yi - rhs }
     }
 }
 
-impl<'a, 'b> Mul<&'b Scalar> for &'a RistrettoShare {
+impl<'b> Mul<&'b Scalar> for &RistrettoShare {
     type Output = RistrettoShare;
     fn mul(self, rhs: &'b Scalar) -> RistrettoShare {
         RistrettoShare { i: self.i, Yi: self.Yi * rhs }
     }
 }
 
+//-----------------------------------------------------------------------------------------------------------
+// ProvedRistrettoShare (Yi = yi*B with a DLEQ proof against the public share yi*G)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProvedRistrettoShare {
+    pub share: RistrettoShare,
+    pub proof: DleqProof
+}
+
+impl ProvedRistrettoShare {
+    pub fn verify(&self, base: &RistrettoPoint, commit: &RistrettoPolynomial) -> bool {
+        let x = Scalar::from(u64::from(self.share.i));
+        self.proof.verify(base, &commit.evaluate(&x), &self.share.Yi)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProvedRistrettoShareVector(pub Vec<ProvedRistrettoShare>);
+
+impl ProvedRistrettoShareVector {
+    pub fn recover(&self, base: &RistrettoPoint, commit: &RistrettoPolynomial) -> Recovered<RistrettoPoint> {
+        let mut valid = Vec::<RistrettoShare>::new();
+        let mut faulty = Vec::<u32>::new();
+        for item in self.0.iter() {
+            if valid.iter().any(|s| s.i == item.share.i) {
+                continue
+            }
+
+            if item.verify(base, commit) {
+                valid.push(item.share.clone());
+            } else {
+                faulty.push(item.share.i);
+            }
+        }
+
+        let value = if valid.len() > commit.degree() {
+            Some(RistrettoShareVector(valid).recover())
+        } else {
+            None
+        };
+
+        Recovered { value, faulty }
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Recovered (result of a recovery that detects and excludes faulty shares)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Recovered<T> {
+    pub value: Option<T>, // None if there are not enough valid shares
+    pub faulty: Vec<u32>
+}
+
+impl<T> Recovered<T> {
+    pub fn ok(self) -> Result<T> {
+        let faulty = self.faulty;
+        self.value.ok_or(Error::NotEnoughShares { faulty })
+    }
+}
 
 //-----------------------------------------------------------------------------------------------------------
 // Shared traits and functions for Polynomial and RistrettoPolynomial
@@ -206,6 +380,138 @@ fn lx_num_bar(range: &[Scalar], i: usize) -> (Vec<Scalar>, Scalar) {
     (num, denum.invert())
 }
 
+fn lx_at(range: &[Scalar], i: usize, x: &Scalar) -> Scalar {
+    let mut num = Scalar::one();
+    let mut denum = Scalar::one();
+    for j in 0..range.len() {
+        if j != i {
+            num *= x - range[j];
+            denum *= range[i] - range[j];
+        }
+    }
+
+    num * denum.invert()
+}
+
+// advance to the next k-combination of 0..n in lexicographic order
+fn next_combination(comb: &mut [usize], n: usize) -> bool {
+    let k = comb.len();
+    for i in (0..k).rev() {
+        if comb[i] < n - k + i {
+            comb[i] += 1;
+            for j in (i + 1)..k {
+                comb[j] = comb[j - 1] + 1;
+            }
+
+            return true
+        }
+    }
+
+    false
+}
+
+// Gaussian elimination over the augmented matrix [A|b], free variables are set to zero
+fn solve(mut m: Vec<Vec<Scalar>>) -> Option<Vec<Scalar>> {
+    let rows = m.len();
+    let cols = m[0].len() - 1;
+
+    let mut pivots = Vec::<usize>::new();
+    for c in 0..cols {
+        let r = pivots.len();
+        if r == rows {
+            break
+        }
+
+        if let Some(k) = (r..rows).find(|k| m[*k][c] != Scalar::zero()) {
+            m.swap(r, k);
+            let inv = m[r][c].invert();
+            for v in m[r].iter_mut() {
+                *v *= inv;
+            }
+
+            let pivot = m[r].clone();
+            for (k, row) in m.iter_mut().enumerate() {
+                let f = row[c];
+                if k != r && f != Scalar::zero() {
+                    for (v, p) in row.iter_mut().zip(pivot.iter()).skip(c) {
+                        *v -= f * p;
+                    }
+                }
+            }
+
+            pivots.push(c);
+        }
+    }
+
+    if m.iter().skip(pivots.len()).any(|row| row[cols] != Scalar::zero()) {
+        return None
+    }
+
+    let mut x = vec![Scalar::zero(); cols];
+    for (r, c) in pivots.iter().enumerate() {
+        x[*c] = m[r][cols];
+    }
+
+    Some(x)
+}
+
+// polynomial division for a monic denominator, returns (quotient, remainder)
+fn div_rem(num: &[Scalar], den: &[Scalar]) -> (Vec<Scalar>, Vec<Scalar>) {
+    let mut rem = num.to_vec();
+    if num.len() < den.len() {
+        return (vec![Scalar::zero()], rem)
+    }
+
+    let dd = den.len() - 1;
+    let mut quot = vec![Scalar::zero(); num.len() - dd];
+    for k in (0..quot.len()).rev() {
+        let f = rem[k + dd];
+        quot[k] = f;
+        for (j, d) in den.iter().enumerate() {
+            rem[k + j] -= f * d;
+        }
+    }
+
+    rem.truncate(dd);
+    (quot, rem)
+}
+
+// find P of degree t such that P(xs[k]) == ys[k] for all but at most "e" points
+fn berlekamp_welch(xs: &[Scalar], ys: &[Scalar], t: usize, e: usize) -> Option<Polynomial> {
+    // unknowns: Q(x) of degree e + t and the monic error locator E(x) of degree e
+    // equations: Q(xk) - yk*E'(xk) = yk*xk^e, where E(x) = x^e + E'(x)
+    let mut m = Vec::<Vec<Scalar>>::with_capacity(xs.len());
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        let mut row = Vec::<Scalar>::with_capacity(2*e + t + 2);
+        let mut xp = Scalar::one();
+        for _ in 0..=(e + t) {
+            row.push(xp);
+            xp *= x;
+        }
+
+        let mut xp = Scalar::one();
+        for _ in 0..e {
+            row.push(-(y * xp));
+            xp *= x;
+        }
+
+        row.push(y * xp);
+        m.push(row);
+    }
+
+    let sol = solve(m)?;
+    let q = &sol[0..=(e + t)];
+    let mut el = sol[(e + t + 1)..].to_vec();
+    el.push(Scalar::one());
+
+    let (p, rem) = div_rem(q, &el);
+    if rem.iter().any(|r| *r != Scalar::zero()) {
+        return None
+    }
+
+    Some(Polynomial { a: p })
+}
+
 pub trait Evaluate {
     type Output;
     fn evaluate(&self, x: &Scalar) -> Self::Output;
@@ -231,7 +537,7 @@ impl Drop for Polynomial {
     }
 }
 
-impl<'a, 'b> Mul<&'b Scalar> for &'a Polynomial {
+impl<'b> Mul<&'b Scalar> for &Polynomial {
     type Output = Polynomial;
     fn mul(self, rhs: &'b Scalar) -> Polynomial {
         Polynomial {
@@ -240,7 +546,7 @@ impl<'a, 'b> Mul<&'b Scalar> for &'a Polynomial {
     }
 }
 
-impl<'a, 'b> Mul<&'b RistrettoPoint> for &'a Polynomial {
+impl<'b> Mul<&'b RistrettoPoint> for &Polynomial {
     type Output = RistrettoPolynomial;
     fn mul(self, rhs: &'b RistrettoPoint) -> RistrettoPolynomial {
         RistrettoPolynomial {
@@ -310,6 +616,7 @@ impl Degree for Polynomial {
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct RistrettoPolynomial {
+    #[serde(with = "crate::crypto::b64::vec")]
     pub A: Vec<RistrettoPoint>
 }
 
@@ -322,7 +629,7 @@ impl Debug for RistrettoPolynomial {
     }
 }
 
-impl<'a, 'b> Mul<&'b Scalar> for &'a RistrettoPolynomial {
+impl<'b> Mul<&'b Scalar> for &RistrettoPolynomial {
     type Output = RistrettoPolynomial;
 
     #[allow(non_snake_case)]
@@ -333,10 +640,26 @@ impl<'a, 'b> Mul<&'b Scalar> for &'a RistrettoPolynomial {
     }
 }
 
+impl<'b> Add<&'b RistrettoPolynomial> for &RistrettoPolynomial {
+    type Output = RistrettoPolynomial;
+
+    #[allow(non_snake_case)]
+    fn add(self, rhs: &'b RistrettoPolynomial) -> RistrettoPolynomial {
+        assert!(self.A.len() == rhs.A.len());
+        RistrettoPolynomial {
+            A: self.A.iter().zip(rhs.A.iter()).map(|(Ak, Bk)| Ak + Bk).collect::<Vec<_>>()
+        }
+    }
+}
+
 impl RistrettoPolynomial {
     pub fn verify(&self, share: &RistrettoShare) -> bool {
-        let x = Scalar::from(u64::from(share.i));
-        share.Yi == self.evaluate(&x)
+        share.Yi == self.public(share.i)
+    }
+
+    // public share "yi * G" of the party i
+    pub fn public(&self, i: u32) -> RistrettoPoint {
+        self.evaluate(&Scalar::from(u64::from(i)))
     }
 }
 
@@ -384,4 +707,80 @@ mod tests {
         let r_S = S_shares.recover();
         assert!(S == r_S);
     }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_proved_recover() {
+        let threshold = 3;
+        let parties = 2*threshold + 1;
+
+        let s = rnd_scalar();
+        let kn = rnd_scalar() * G;
+
+        let poly = Polynomial::rnd(s, threshold);
+        let commit = &poly * &G;
+
+        let shares = poly.shares(parties);
+        let mut S_shares = shares.prove(&kn);
+
+        let r_S = S_shares.recover(&kn, &commit);
+        assert!(r_S.value == Some(s * kn) && r_S.faulty.is_empty());
+
+        // curators 2 and 5 lie about their partial results
+        S_shares.0[1].share.Yi += G;
+        S_shares.0[4] = shares.0[0].prove(&kn);
+        S_shares.0[4].share.i = 5;
+
+        let r_S = S_shares.recover(&kn, &commit);
+        assert!(r_S.value == Some(s * kn) && r_S.faulty == vec![2, 5]);
+
+        // not enough valid shares
+        let r_S = ProvedRistrettoShareVector(S_shares.0[0..4].to_vec()).recover(&kn, &commit);
+        assert!(r_S.value.is_none() && r_S.faulty == vec![2]);
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_robust_recover() {
+        let threshold = 4;
+        let parties = 2*threshold + 1;
+
+        let s = rnd_scalar();
+        let kn = rnd_scalar() * G;
+
+        let poly = Polynomial::rnd(s, threshold);
+        let commit = &poly * &G;
+
+        let mut shares = poly.shares(parties);
+        let r_s = shares.recover_robust(threshold);
+        assert!(r_s.value == Some(s) && r_s.faulty.is_empty());
+
+        // Berlekamp-Welch corrects up to t/2 faulty shares
+        shares.0[2].yi += Scalar::one();
+        shares.0[6].yi = rnd_scalar();
+
+        let r_s = shares.recover_robust(threshold);
+        assert!(r_s.value == Some(s) && r_s.faulty == vec![3, 7]);
+
+        let mut S_shares = &shares * &kn;
+        let r_S = S_shares.recover_robust(threshold);
+        assert!(r_S.value == Some(s * kn) && r_S.faulty == vec![3, 7]);
+
+        // the commitment corrects up to t faulty shares
+        shares.0[0].yi = rnd_scalar();
+        shares.0[8].yi = rnd_scalar();
+
+        let r_s = shares.recover_checked(&commit);
+        assert!(r_s.value == Some(s) && r_s.faulty == vec![1, 3, 7, 9]);
+
+        // too many faulty shares for Berlekamp-Welch
+        S_shares.0[0].Yi = G;
+        let r_S = S_shares.recover_robust(threshold);
+        assert!(r_S.value != Some(s * kn));
+
+        // the search is bounded
+        let S_shares = &poly.shares(ROBUST_MAX_SHARES + 1) * &kn;
+        assert!(S_shares.recover_robust(threshold).value.is_none());
+        assert!(RistrettoShareVector(S_shares.0[..ROBUST_MAX_SHARES].to_vec()).recover_robust(threshold).value == Some(s * kn));
+    }
 }
\ No newline at end of file
@@ -113,6 +113,7 @@ impl Signature {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ExtSignature {
     pub sig: Signature,
+    #[serde(with = "crate::crypto::b64")]
     pub key: RistrettoPoint
 }
 
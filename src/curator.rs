@@ -0,0 +1,612 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use sha2::{Sha512, Digest};
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::codec::{encode, decode, to_json, from_json};
+use crate::consent::{ConsentToken, AccessRequest};
+use crate::crypto::{G, KeyPair};
+use crate::crypto::dkg::DkgOutput;
+use crate::crypto::shares::*;
+use crate::crypto::signatures::ExtSignature;
+use crate::emergency::*;
+use crate::merkle::*;
+use crate::store::*;
+use crate::structs::*;
+
+const MAX_BODY: u64 = 16 * 1024 * 1024;
+const AUDIT_FILE: &str = "audit.log";
+const IDENTITY_FILE: &str = "identity.key";
+
+//-----------------------------------------------------------------------------------------------------------
+// Curator API messages
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CuratorInfo {
+    pub i: u32,
+    pub commit: RistrettoPolynomial,
+    #[serde(with = "crate::crypto::b64")]
+    pub identity: RistrettoPoint // signs the audit log and the tree heads
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainQuery {
+    pub id: String,
+    pub set: String
+}
+
+// tail records don't carry the (id, set), the request does
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AppendRequest {
+    pub id: String,
+    pub set: String,
+    pub rn: Rn
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AppendResponse {
+    pub len: usize
+}
+
+// signed by the curator i, evidence of the replica it served
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TreeHead {
+    pub id: String,
+    pub set: String,
+    pub i: u32,
+    pub size: usize,
+    pub root: Vec<u8>,
+    sig: ExtSignature
+}
+
+impl TreeHead {
+    pub fn new(curator: &KeyPair, i: u32, id: &str, set: &str, size: usize, root: Vec<u8>) -> Self {
+        let dhash = Self::hash(id, set, i, size, &root);
+        let sig = ExtSignature::sign(&curator.s, curator.key, &dhash);
+        Self { id: id.into(), set: set.into(), i, size, root, sig }
+    }
+
+    // "identity" is the trusted identity key of the curator i
+    pub fn verify(&self, identity: &RistrettoPoint) -> Result<()> {
+        if &self.sig.key != identity {
+            return Err(Error::Mismatch)
+        }
+
+        let dhash = Self::hash(&self.id, &self.set, self.i, self.size, &self.root);
+        if !self.sig.verify(&dhash) {
+            return Err(Error::InvalidSignature)
+        }
+
+        Ok(())
+    }
+
+    fn hash(id: &str, set: &str, i: u32, size: usize, root: &[u8]) -> Vec<u8> {
+        Sha512::new()
+            .chain(id)
+            .chain([0u8])
+            .chain(set)
+            .chain(i.to_le_bytes())
+            .chain((size as u64).to_le_bytes())
+            .chain(root)
+            .result().to_vec()
+    }
+}
+
+// proofs against the tree of the first "size" records
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InclusionQuery {
+    pub id: String,
+    pub set: String,
+    pub index: usize,
+    pub size: usize
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConsistencyQuery {
+    pub id: String,
+    pub set: String,
+    pub first: usize,
+    pub second: usize
+}
+
+// the share is only released with the consent of the data-subject (owned chains), a request of a grantee
+// configured in the curator (other chains) or an audited emergency request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Authorization {
+    Consent(Box<ConsentToken>),
+    Access(Box<AccessRequest>),
+    Emergency(Box<EmergencyRequest>)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PartialRequest {
+    pub id: String,
+    pub set: String,
+    pub auth: Authorization
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ErrorResponse {
+    error: String
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Curator (holds one share of the master key, a local RnStore and the audit log of emergency requests)
+// NOTE: the audit log and the tree heads are signed with the identity key of the node, not with the share.
+// The share changes with a refresh or reshare, the identity is kept in the store directory.
+//-----------------------------------------------------------------------------------------------------------
+pub struct Curator {
+    output: DkgOutput,
+    identity: KeyPair,
+    store: RnStore,
+    segments: HashMap<(String, String), RnSegment>,
+    audit: AuditLog,
+    grantees: Vec<RistrettoPoint>, // allowed to sign access requests
+    requesters: Vec<RistrettoPoint> // allowed to sign emergency requests
+}
+
+impl Curator {
+    pub fn new(output: DkgOutput, store: RnStore) -> Result<Self> {
+        let identity = load_identity(store.dir())?;
+        let path = store.dir().join(AUDIT_FILE);
+
+        let audit = match std::fs::read(&path) {
+            Ok(data) => decode::<AuditLog>(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AuditLog::new(),
+            Err(e) => return Err(Error::Io(e))
+        };
+
+        audit.verify(&identity.key)?;
+        Ok(Self { output, identity, store, segments: HashMap::new(), audit, grantees: Vec::new(), requesters: Vec::new() })
+    }
+
+    // without grantees only owned chains can be recovered outside of an emergency
+    pub fn with_grantees(mut self, grantees: Vec<RistrettoPoint>) -> Self {
+        self.grantees = grantees;
+        self
+    }
+
+    // without requesters every emergency request is refused
+    pub fn with_requesters(mut self, requesters: Vec<RistrettoPoint>) -> Self {
+        self.requesters = requesters;
+        self
+    }
+
+    pub fn info(&self) -> CuratorInfo {
+        CuratorInfo { i: self.output.share.i, commit: self.output.commit.clone(), identity: self.identity.key }
+    }
+
+    pub fn identity(&self) -> &RistrettoPoint {
+        &self.identity.key
+    }
+
+    pub fn append(&mut self, req: AppendRequest) -> Result<AppendResponse> {
+        if req.rn.id.is_some() {
+            if req.rn.id.as_deref() != Some(req.id.as_str()) || req.rn.set.as_deref() != Some(req.set.as_str()) {
+                return Err(Error::Mismatch)
+            }
+
+            let seg = self.store.create(req.rn)?;
+            self.segments.insert((req.id, req.set), seg);
+            return Ok(AppendResponse { len: 1 })
+        }
+
+        let seg = self.segment(&req.id, &req.set)?;
+        seg.push(req.rn)?;
+        Ok(AppendResponse { len: seg.chain.chain.len() })
+    }
+
+    pub fn chain(&mut self, query: &ChainQuery) -> Result<RnChain> {
+        Ok(self.segment(&query.id, &query.set)?.chain.clone())
+    }
+
+    pub fn tree(&mut self, query: &ChainQuery) -> Result<TreeHead> {
+        let chain = &self.segment(&query.id, &query.set)?.chain;
+        let (size, root) = (chain.chain.len(), chain.root());
+        Ok(TreeHead::new(&self.identity, self.output.share.i, &query.id, &query.set, size, root))
+    }
+
+    pub fn inclusion(&mut self, query: &InclusionQuery) -> Result<InclusionProof> {
+        self.segment(&query.id, &query.set)?.chain.tree().inclusion(query.index, query.size)
+    }
+
+    pub fn consistency(&mut self, query: &ConsistencyQuery) -> Result<ConsistencyProof> {
+        self.segment(&query.id, &query.set)?.chain.tree().consistency(query.first, query.second)
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    // "yi * kn" for the last record of the chain, with a proof against the commitment
+    pub fn partial(&mut self, req: &PartialRequest, now: u64) -> Result<ProvedRistrettoShare> {
+        self.segment(&req.id, &req.set)?;
+        let chain = &self.segments[&(req.id.clone(), req.set.clone())].chain;
+
+        match &req.auth {
+            Authorization::Consent(token) => {
+                token.verify(chain, now)?;
+                Ok(self.output.share.prove(chain.kn()))
+            },
+            Authorization::Access(request) => {
+                request.verify(chain, &self.grantees, now)?;
+                Ok(self.output.share.prove(chain.kn()))
+            },
+            Authorization::Emergency(request) => {
+                // the response is only released after the entry is durable
+                let (lhash, len) = (self.audit.lhash.clone(), self.audit.entries.len());
+                let response = self.audit.respond(&self.identity, &self.output.share, chain, request, &self.requesters, now)?;
+                if let Err(e) = self.save_audit() {
+                    self.audit.lhash = lhash;
+                    self.audit.entries.truncate(len);
+                    return Err(e)
+                }
+
+                Ok(response)
+            }
+        }
+    }
+
+    pub fn spawn(self, addr: &str) -> Result<CuratorNode> {
+        let server = Server::http(addr).map_err(|e| Error::Io(std::io::Error::other(e.to_string())))?;
+        let addr = server.server_addr().to_ip().ok_or(Error::InvalidParameter("Unsupported listen address!"))?;
+
+        let server = Arc::new(server);
+        let handle = {
+            let server = server.clone();
+            let mut curator = self;
+            std::thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let mut body = String::new();
+                    let res = request.as_reader().take(MAX_BODY).read_to_string(&mut body);
+
+                    let (status, reply) = match res {
+                        Ok(_) => curator.handle(request.method(), request.url(), &body),
+                        Err(e) => reply::<()>(Err(Error::Io(e)))
+                    };
+
+                    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+                    let response = Response::from_string(reply).with_status_code(status).with_header(header);
+                    request.respond(response).ok();
+                }
+            })
+        };
+
+        Ok(CuratorNode { addr, server, handle: Some(handle) })
+    }
+
+    fn handle(&mut self, method: &Method, url: &str, body: &str) -> (u16, String) {
+        match (method, url) {
+            (Method::Get, "/info") => reply(Ok(self.info())),
+            (Method::Post, "/append") => reply(from_json(body).and_then(|req| self.append(req))),
+            (Method::Post, "/chain") => reply(from_json(body).and_then(|q| self.chain(&q))),
+            (Method::Post, "/tree") => reply(from_json(body).and_then(|q| self.tree(&q))),
+            (Method::Post, "/inclusion") => reply(from_json(body).and_then(|q| self.inclusion(&q))),
+            (Method::Post, "/consistency") => reply(from_json(body).and_then(|q| self.consistency(&q))),
+            (Method::Post, "/partial") => reply(from_json(body).and_then(|req| self.partial(&req, unix_now()))),
+            _ => (404, to_json(&ErrorResponse { error: "Unknown endpoint!".into() }).unwrap())
+        }
+    }
+
+    fn segment(&mut self, id: &str, set: &str) -> Result<&mut RnSegment> {
+        let key = (id.to_string(), set.to_string());
+        if !self.segments.contains_key(&key) {
+            let seg = self.store.load(id, set)?;
+            self.segments.insert(key.clone(), seg);
+        }
+
+        Ok(self.segments.get_mut(&key).unwrap())
+    }
+
+    // replaced with a rename, a crash leaves the previous or the new log
+    fn save_audit(&self) -> Result<()> {
+        let path = self.store.dir().join(AUDIT_FILE);
+        let tmp = self.store.dir().join(format!("{}.tmp", AUDIT_FILE));
+
+        let mut file = File::create(&tmp)?;
+        file.write_all(&encode(&self.audit)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)?;
+
+        File::open(self.store.dir()).and_then(|d| d.sync_all()).ok();
+        Ok(())
+    }
+}
+
+// created on the first start, a crash before the rename leaves no identity
+fn load_identity(dir: &Path) -> Result<KeyPair> {
+    let path = dir.join(IDENTITY_FILE);
+    match std::fs::read(&path) {
+        Ok(data) => {
+            let bytes: [u8; 32] = data.as_slice().try_into().map_err(|_| Error::MalformedKey)?;
+            let s = Scalar::from_canonical_bytes(bytes).ok_or(Error::MalformedKey)?;
+            return Ok(KeyPair { s, key: s * G })
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(Error::Io(e))
+    }
+
+    // the secret key is only readable by the owner
+    let tmp = dir.join(format!("{}.tmp", IDENTITY_FILE));
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let identity = KeyPair::new();
+    let mut file = options.open(&tmp)?;
+    file.write_all(identity.s.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, &path)?;
+
+    File::open(dir).and_then(|d| d.sync_all()).ok();
+    Ok(identity)
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn reply<T: Serialize>(res: Result<T>) -> (u16, String) {
+    let err = match res.and_then(|value| to_json(&value)) {
+        Ok(json) => return (200, json),
+        Err(err) => err
+    };
+
+    let status = match &err {
+        Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound => 404,
+        Error::Io(e) if e.kind() == std::io::ErrorKind::AlreadyExists => 409,
+        Error::Io(_) | Error::Corrupted { .. } => 500,
+        Error::NotOwned | Error::SubjectRequired | Error::Expired | Error::UnknownRequester | Error::InvalidSignature | Error::InvalidProof => 403,
+        _ => 400
+    };
+
+    (status, to_json(&ErrorResponse { error: err.to_string() }).unwrap())
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// CuratorNode (the running HTTP server, stopped on drop)
+//-----------------------------------------------------------------------------------------------------------
+pub struct CuratorNode {
+    addr: SocketAddr,
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl CuratorNode {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // blocks while the server is running
+    pub fn join(mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+impl Drop for CuratorNode {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Minimal HTTP/1.1 client for the curator API (one request per connection)
+//-----------------------------------------------------------------------------------------------------------
+pub fn call<T: Serialize, O: DeserializeOwned>(addr: &str, path: &str, body: Option<&T>, timeout: Duration) -> Result<O> {
+    let sock = addr.to_socket_addrs()?.next().ok_or(Error::InvalidParameter("Invalid curator address!"))?;
+    let mut stream = TcpStream::connect_timeout(&sock, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let (method, body) = match body {
+        Some(body) => ("POST", to_json(body)?),
+        None => ("GET", String::new())
+    };
+
+    let head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", method, path, addr, body.len());
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+
+    let mut data = Vec::new();
+    stream.take(MAX_BODY).read_to_end(&mut data)?;
+
+    let text = String::from_utf8(data).map_err(|_| Error::InvalidParameter("Invalid HTTP response!"))?;
+    let (head, body) = text.split_once("\r\n\r\n").ok_or(Error::InvalidParameter("Invalid HTTP response!"))?;
+    let status: u16 = head.split(' ').nth(1).and_then(|s| s.parse().ok()).ok_or(Error::InvalidParameter("Invalid HTTP response!"))?;
+
+    if status != 200 {
+        let message = from_json::<ErrorResponse>(body).map(|e| e.error).unwrap_or_default();
+        return Err(Error::Remote { status, message })
+    }
+
+    from_json(body)
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::crypto::*;
+    use crate::crypto::dkg::*;
+    use crate::crypto::refresh::RefreshParty;
+
+    pub(crate) fn tmp_dir() -> std::path::PathBuf {
+        let name = base64::encode_config(&rnd_scalar().as_bytes()[0..8], base64::URL_SAFE_NO_PAD);
+        std::env::temp_dir().join(format!("curator-{}", name))
+    }
+
+    #[test]
+    fn curator_nodes() {
+        let skp = KeyPair::new(); // source key-pair
+        let timeout = Duration::from_secs(5);
+
+        let outputs = federation(1, 3);
+        let ekey = outputs[0].key();
+        let commit = outputs[0].commit.clone();
+
+        let gkp = KeyPair::new(); // grantee key-pair
+        let rkp = KeyPair::new(); // requester key-pair
+        let dirs: Vec<_> = (0..3).map(|_| tmp_dir()).collect();
+        let nodes: Vec<CuratorNode> = outputs.iter().cloned().zip(dirs.iter())
+            .map(|(o, dir)| Curator::new(o, RnStore::open(dir).unwrap()).unwrap().with_grantees(vec![gkp.key]).with_requesters(vec![rkp.key]).spawn("127.0.0.1:0").unwrap())
+            .collect();
+        let addrs: Vec<String> = nodes.iter().map(|n| n.addr().to_string()).collect();
+
+        // replicate a chain in all nodes
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-1-url".to_vec() } };
+        let (lamb, head) = Rn::head(&skp, &ekey, "subject-id", "dataset-id", rd);
+        let mut chain = RnChain::new(head.clone()).unwrap();
+
+        let rd = RnData { lambda_prev: Some(lamb), file: RnFileRef { dn: *b"encryption654321", hfile: b"file-2-url".to_vec() } };
+        let (_, tail) = chain.tail(&skp, &ekey, rd);
+        let head_root = chain.root();
+        chain.push(tail.clone()).unwrap();
+
+        for addr in addrs.iter() {
+            for rn in [head.clone(), tail.clone()] {
+                let req = AppendRequest { id: "subject-id".into(), set: "dataset-id".into(), rn };
+                call::<_, AppendResponse>(addr, "/append", Some(&req), timeout).unwrap();
+            }
+        }
+
+        // records that don't extend the chain are rejected
+        let req = AppendRequest { id: "subject-id".into(), set: "dataset-id".into(), rn: tail };
+        let res = call::<_, AppendResponse>(&addrs[0], "/append", Some(&req), timeout);
+        assert!(matches!(res, Err(Error::Remote { status: 400, .. })));
+
+        // fetch the chain and recover alpha from 2 nodes
+        let query = ChainQuery { id: "subject-id".into(), set: "dataset-id".into() };
+        let remote: RnChain = call(&addrs[2], "/chain", Some(&query), timeout).unwrap();
+        assert!(remote.lhash == chain.lhash);
+
+        // record and consistency proofs without the chain
+        let infos: Vec<CuratorInfo> = addrs.iter().map(|a| call::<(), _>(a, "/info", None, timeout).unwrap()).collect();
+        let head: TreeHead = call(&addrs[0], "/tree", Some(&query), timeout).unwrap();
+        assert!(head.size == 2 && head.root == chain.root() && head.verify(&infos[0].identity).is_ok());
+
+        let forged = TreeHead { size: 1, root: head_root.clone(), ..head.clone() };
+        assert!(matches!(forged.verify(&infos[0].identity), Err(Error::InvalidSignature)));
+        assert!(matches!(head.verify(&infos[1].identity), Err(Error::Mismatch)));
+
+        let q = InclusionQuery { id: "subject-id".into(), set: "dataset-id".into(), index: 1, size: 2 };
+        let proof: InclusionProof = call(&addrs[0], "/inclusion", Some(&q), timeout).unwrap();
+        assert!(proof.verify(&chain.lhash, &head.root));
+
+        let q = ConsistencyQuery { id: "subject-id".into(), set: "dataset-id".into(), first: 1, second: 2 };
+        let proof: ConsistencyProof = call(&addrs[0], "/consistency", Some(&q), timeout).unwrap();
+        assert!(proof.verify(&head_root, &head.root));
+
+        assert!(infos[1].i == 2 && infos[1].commit == commit);
+
+        // partial shares need an authorization
+        assert!(matches!(call::<_, ProvedRistrettoShare>(&addrs[1], "/partial", Some(&query), timeout), Err(Error::Remote { status: 400, .. })));
+
+        let token = ConsentToken::new(&gkp, &remote, &gkp.key, u64::MAX);
+        let req = PartialRequest { id: "subject-id".into(), set: "dataset-id".into(), auth: Authorization::Consent(Box::new(token)) };
+        assert!(matches!(call::<_, ProvedRistrettoShare>(&addrs[1], "/partial", Some(&req), timeout), Err(Error::Remote { status: 403, .. })));
+
+        // ordinary recovery by a configured grantee, not by anyone else
+        let request = AccessRequest::new(&gkp, &remote, unix_now() - 10);
+        let req = PartialRequest { auth: Authorization::Access(Box::new(request)), ..req };
+        let partials: Vec<ProvedRistrettoShare> = addrs[..2].iter().map(|a| call(a, "/partial", Some(&req), timeout).unwrap()).collect();
+        let alpha = ProvedRistrettoShareVector(partials).recover(remote.kn(), &commit).ok().unwrap();
+        assert!(remote.recover(&alpha.compress()).unwrap()[1].hfile == b"file-2-url");
+
+        let request = AccessRequest::new(&rkp, &remote, unix_now() - 10);
+        let req = PartialRequest { auth: Authorization::Access(Box::new(request)), ..req };
+        assert!(matches!(call::<_, ProvedRistrettoShare>(&addrs[1], "/partial", Some(&req), timeout), Err(Error::Remote { status: 403, .. })));
+
+        let request = EmergencyRequest::new(&gkp, &remote, "unconscious patient in ER", unix_now() - 10);
+        let req = PartialRequest { auth: Authorization::Emergency(Box::new(request)), ..req };
+        assert!(matches!(call::<_, ProvedRistrettoShare>(&addrs[1], "/partial", Some(&req), timeout), Err(Error::Remote { status: 403, .. })));
+
+        let request = EmergencyRequest::new(&rkp, &remote, "unconscious patient in ER", unix_now() - 10);
+        let req = PartialRequest { auth: Authorization::Emergency(Box::new(request)), ..req };
+        let partials: Vec<ProvedRistrettoShare> = addrs[1..].iter().map(|a| call(a, "/partial", Some(&req), timeout).unwrap()).collect();
+        let alpha = ProvedRistrettoShareVector(partials.clone()).recover(remote.kn(), &commit).ok().unwrap();
+        assert!(remote.recover(&alpha.compress()).unwrap()[1].hfile == b"file-2-url");
+
+        let expired = EmergencyRequest::new(&rkp, &remote, "unconscious patient in ER", unix_now() - 2 * EMERGENCY_WINDOW);
+        let req = PartialRequest { auth: Authorization::Emergency(Box::new(expired)), ..req };
+        assert!(matches!(call::<_, ProvedRistrettoShare>(&addrs[1], "/partial", Some(&req), timeout), Err(Error::Remote { status: 403, .. })));
+
+        // unknown chain
+        let query = ChainQuery { id: "other-id".into(), set: "dataset-id".into() };
+        let res = call::<_, RnChain>(&addrs[0], "/chain", Some(&query), timeout);
+        assert!(matches!(res, Err(Error::Remote { status: 404, .. })));
+
+        // the audit log is persisted and signed with the identity of the node
+        drop(nodes);
+        let curator = Curator::new(outputs[1].clone(), RnStore::open(&dirs[1]).unwrap()).unwrap();
+        let entries = curator.audit().entries("subject-id", "dataset-id");
+        assert!(entries.len() == 1 && entries[0].matches(&partials[0]) && entries[0].curator() == &infos[1].identity);
+
+        for dir in dirs {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn curator_restart_after_refresh() {
+        let skp = KeyPair::new(); // source key-pair
+        let rkp = KeyPair::new(); // requester key-pair
+        let dir = tmp_dir();
+
+        let outputs = federation(1, 3);
+        let mut curator = Curator::new(outputs[0].clone(), RnStore::open(&dir).unwrap()).unwrap().with_requesters(vec![rkp.key]);
+        let identity = *curator.identity();
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, head) = Rn::head(&skp, &outputs[0].key(), "subject-id", "dataset-id", rd);
+        let chain = RnChain::new(head.clone()).unwrap();
+        curator.append(AppendRequest { id: "subject-id".into(), set: "dataset-id".into(), rn: head }).unwrap();
+
+        let request = EmergencyRequest::new(&rkp, &chain, "unconscious patient in ER", 1000);
+        let req = PartialRequest { id: "subject-id".into(), set: "dataset-id".into(), auth: Authorization::Emergency(Box::new(request)) };
+        curator.partial(&req, 1010).unwrap();
+        drop(curator);
+
+        // proactive refresh of all the shares, the node restarts with a new share
+        let mut parties: Vec<RefreshParty> = outputs.iter().map(|o| RefreshParty::new(1, o.clone())).collect();
+        let deals: Vec<_> = parties.iter().map(|p| p.deal(3)).collect();
+        for (deal, shares) in deals.iter() {
+            for (party, share) in parties.iter_mut().zip(shares.0.iter()) {
+                party.receive(deal, share.clone());
+            }
+        }
+
+        let refreshed = parties[0].finish(&[]).unwrap();
+        assert!(refreshed.share.yi != outputs[0].share.yi);
+
+        let mut curator = Curator::new(refreshed, RnStore::open(&dir).unwrap()).unwrap();
+        assert!(curator.identity() == &identity && curator.audit().entries[0].curator() == &identity);
+
+        let query = ChainQuery { id: "subject-id".into(), set: "dataset-id".into() };
+        assert!(curator.tree(&query).unwrap().verify(&identity).is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
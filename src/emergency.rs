@@ -0,0 +1,264 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use sha2::{Sha512, Digest};
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use crate::crypto::*;
+use crate::crypto::shares::*;
+use crate::crypto::signatures::*;
+use crate::structs::*;
+
+pub const EMERGENCY_WINDOW: u64 = 300; // max age of a request in seconds
+
+//-----------------------------------------------------------------------------------------------------------
+// EmergencyRequest (break-the-glass request signed by the requester)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmergencyRequest {
+    pub id: String,
+    pub set: String,
+    #[serde(with = "crate::crypto::b64")]
+    pub kn: RistrettoPoint,
+    pub timestamp: u64, // unix time in seconds
+    pub reason: String,
+    sig: ExtSignature
+}
+
+impl EmergencyRequest {
+    pub fn new(requester: &KeyPair, chain: &RnChain, reason: &str, timestamp: u64) -> Self {
+        let kn = *chain.kn();
+        let dhash = Self::hash(chain.id(), chain.set(), &kn, timestamp, reason);
+        let sig = ExtSignature::sign(&requester.s, requester.key, &dhash);
+
+        Self { id: chain.id().into(), set: chain.set().into(), kn, timestamp, reason: reason.into(), sig }
+    }
+
+    pub fn requester(&self) -> &RistrettoPoint {
+        &self.sig.key
+    }
+
+    // "requesters" are the keys allowed to break the glass, configured by the curator
+    // NOTE: owned chains are not supported, alpha = k*(ekey + subject) can't be recovered without the data-subject
+    pub fn verify(&self, chain: &RnChain, requesters: &[RistrettoPoint], now: u64) -> Result<()> {
+        if chain.subject().is_some() {
+            return Err(Error::SubjectRequired)
+        }
+
+        if self.id != chain.id() || self.set != chain.set() || &self.kn != chain.kn() {
+            return Err(Error::Mismatch)
+        }
+
+        if self.timestamp > now || now - self.timestamp > EMERGENCY_WINDOW {
+            return Err(Error::Expired)
+        }
+
+        if !requesters.contains(self.requester()) {
+            return Err(Error::UnknownRequester)
+        }
+
+        let dhash = Self::hash(&self.id, &self.set, &self.kn, self.timestamp, &self.reason);
+        if !self.sig.verify(&dhash) {
+            return Err(Error::InvalidSignature)
+        }
+
+        Ok(())
+    }
+
+    fn hash(id: &str, set: &str, kn: &RistrettoPoint, timestamp: u64, reason: &str) -> Vec<u8> {
+        Sha512::new()
+            .chain(id)
+            .chain(set)
+            .chain(kn.compress().as_bytes())
+            .chain(timestamp.to_le_bytes())
+            .chain(reason)
+            .result().to_vec()
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// AuditEntry (request and response, signed by the curator and linked to the previous entry)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub hprev: Vec<u8>,
+    pub request: EmergencyRequest,
+    pub response: Vec<u8>, // H(response), any t+1 logs would otherwise recover alpha
+    sig: ExtSignature
+}
+
+impl AuditEntry {
+    pub fn curator(&self) -> &RistrettoPoint {
+        &self.sig.key
+    }
+
+    // the requester can check that the received response was logged
+    pub fn matches(&self, response: &ProvedRistrettoShare) -> bool {
+        self.response == Self::response_hash(response)
+    }
+
+    pub fn hash(&self) -> Vec<u8> {
+        Self::entry_hash(&self.hprev, &self.request, &self.response)
+    }
+
+    fn entry_hash(hprev: &[u8], request: &EmergencyRequest, response: &[u8]) -> Vec<u8> {
+        let request = bincode::serialize(request).unwrap();
+
+        Sha512::new()
+            .chain(hprev)
+            .chain(request)
+            .chain(response)
+            .result().to_vec()
+    }
+
+    fn response_hash(response: &ProvedRistrettoShare) -> Vec<u8> {
+        let response = bincode::serialize(response).unwrap();
+        Sha512::digest(&response).to_vec()
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// AuditLog (tamper-evident log of a curator)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditLog {
+    pub lhash: Vec<u8>, // last entry hash
+    pub entries: Vec<AuditEntry>
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the response is only released after being appended to the log
+    pub fn respond(&mut self, curator: &KeyPair, share: &Share, chain: &RnChain, request: &EmergencyRequest, requesters: &[RistrettoPoint], now: u64) -> Result<ProvedRistrettoShare> {
+        request.verify(chain, requesters, now)?;
+
+        let response = share.prove(chain.kn());
+        let hresponse = AuditEntry::response_hash(&response);
+        let dhash = AuditEntry::entry_hash(&self.lhash, request, &hresponse);
+        let sig = ExtSignature::sign(&curator.s, curator.key, &dhash);
+
+        self.entries.push(AuditEntry { hprev: self.lhash.clone(), request: request.clone(), response: hresponse, sig });
+        self.lhash = dhash;
+
+        Ok(response)
+    }
+
+    // verified by the data-subject with the public key of the curator
+    pub fn verify(&self, curator: &RistrettoPoint) -> Result<()> {
+        let mut lhash = Vec::<u8>::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            if entry.hprev != lhash {
+                return Err(Error::BrokenChain { index })
+            }
+
+            lhash = entry.hash();
+            if entry.curator() != curator || !entry.sig.verify(&lhash) {
+                return Err(Error::InvalidSignature)
+            }
+        }
+
+        if lhash != self.lhash {
+            return Err(Error::BrokenChain { index: self.entries.len() })
+        }
+
+        Ok(())
+    }
+
+    pub fn entries(&self, id: &str, set: &str) -> Vec<&AuditEntry> {
+        self.entries.iter().filter(|e| e.request.id == id && e.request.set == set).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emergency_recover() {
+        let skp = KeyPair::new(); // source key-pair
+        let rkp = KeyPair::new(); // requester key-pair
+        let ckp: Vec<KeyPair> = (0..3).map(|_| KeyPair::new()).collect(); // curators key-pairs
+
+        let ekp = KeyPair::new(); // master key-pair
+        let poly = Polynomial::rnd(ekp.s, 1);
+        let commit = &poly * &G;
+        let ei = poly.shares(3);
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd.clone());
+        let chain = RnChain::new(r).unwrap();
+
+        let request = EmergencyRequest::new(&rkp, &chain, "unconscious patient in ER", 1000);
+
+        let mut logs: Vec<AuditLog> = (0..3).map(|_| AuditLog::new()).collect();
+        let responses: Vec<ProvedRistrettoShare> = logs.iter_mut().zip(ckp.iter()).zip(ei.0.iter())
+            .map(|((log, kp), share)| log.respond(kp, share, &chain, &request, &[rkp.key], 1010).unwrap())
+            .collect();
+
+        // the logs only keep a hash of the released shares
+        assert!(logs.iter().zip(responses.iter()).all(|(log, res)| log.entries[0].matches(res)));
+
+        let alpha = ProvedRistrettoShareVector(responses).recover(chain.kn(), &commit).value.unwrap();
+        assert!(chain.recover(&alpha.compress()).unwrap()[0] == rd.file);
+
+        // the data-subject verifies the logs
+        for (log, kp) in logs.iter().zip(ckp.iter()) {
+            assert!(log.verify(&kp.key).is_ok());
+
+            let entries = log.entries("subject-id", "dataset-id");
+            assert!(entries.len() == 1 && entries[0].request.requester() == &rkp.key);
+        }
+
+        // a removed entry is detected
+        let mut log = logs[0].clone();
+        log.entries.clear();
+        assert!(log.verify(&ckp[0].key).is_err());
+
+        // a modified entry is detected
+        let mut log = logs[1].clone();
+        log.entries[0].request.reason = "routine check".into();
+        assert!(log.verify(&ckp[1].key).is_err());
+    }
+
+    #[test]
+    fn emergency_invalid_request() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+        let rkp = KeyPair::new(); // requester key-pair
+        let ckp = KeyPair::new(); // curator key-pair
+        let share = Polynomial::rnd(ekp.s, 1).shares(1).0[0].clone();
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd.clone());
+        let chain = RnChain::new(r).unwrap();
+
+        let mut log = AuditLog::new();
+
+        // stale request
+        let request = EmergencyRequest::new(&rkp, &chain, "unconscious patient in ER", 1000);
+        assert!(matches!(log.respond(&ckp, &share, &chain, &request, &[rkp.key], 1000 + EMERGENCY_WINDOW + 1), Err(Error::Expired)));
+
+        // modified request
+        let mut request = EmergencyRequest::new(&rkp, &chain, "unconscious patient in ER", 1000);
+        request.reason = "other".into();
+        assert!(log.respond(&ckp, &share, &chain, &request, &[rkp.key], 1000).is_err());
+
+        // signed by a key unknown to the curator
+        let request = EmergencyRequest::new(&KeyPair::new(), &chain, "unconscious patient in ER", 1000);
+        assert!(matches!(log.respond(&ckp, &share, &chain, &request, &[rkp.key], 1000), Err(Error::UnknownRequester)));
+
+        // owned chains require the data-subject, alpha also depends on its secret key (not supported)
+        let (_, r) = Rn::owned_head(&skp, &ekp.key, &KeyPair::new().key, "subject-id", "dataset-id", rd);
+        let owned = RnChain::new(r).unwrap();
+        let request = EmergencyRequest::new(&rkp, &owned, "unconscious patient in ER", 1000);
+        assert!(matches!(log.respond(&ckp, &share, &owned, &request, &[rkp.key], 1000), Err(Error::SubjectRequired)));
+
+        assert!(log.entries.is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use std::fmt::{Display, Formatter};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+//-----------------------------------------------------------------------------------------------------------
+// Error
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    InvalidSignature,
+    InvalidProof,
+    BrokenChain { index: usize },
+    NotHead,
+    NotTail,
+    EmptyChain,
+    DecryptionFailed,
+    UnsupportedVersion,
+    Truncated,
+    Corrupted { offset: usize }, // stored data with an invalid checksum or encoding
+    MalformedKey,
+    NotEnoughShares { faulty: Vec<u32> },
+    NotOwned, // the chain has no data-subject
+    SubjectRequired, // the chain can't be opened without the data-subject
+    Mismatch, // a token or request for another chain
+    Expired,
+    Unauthorized, // signer not allowed by the chain policy
+    UnexpectedSigner, // file signed by another source
+    UnknownRequester, // request signer not configured in the curator
+    InvalidParameter(&'static str),
+    Remote { status: u16, message: String }, // error reply of a curator node
+    Io(std::io::Error),
+    Codec(bincode::Error) // bincode and JSON encodings
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidSignature => fmt.write_str("Invalid signature!"),
+            Error::InvalidProof => fmt.write_str("Invalid proof!"),
+            Error::BrokenChain { index } => write!(fmt, "Incorrect hash chain at {}!", index),
+            Error::NotHead => fmt.write_str("Record is not a head type!"),
+            Error::NotTail => fmt.write_str("Record is not a tail type!"),
+            Error::EmptyChain => fmt.write_str("Empty chain!"),
+            Error::DecryptionFailed => fmt.write_str("Authentication failed!"),
+            Error::UnsupportedVersion => fmt.write_str("Unsupported version!"),
+            Error::Truncated => fmt.write_str("Truncated file!"),
+            Error::Corrupted { offset } => write!(fmt, "Corrupted data at {}!", offset),
+            Error::MalformedKey => fmt.write_str("Malformed key!"),
+            Error::NotEnoughShares { faulty } => write!(fmt, "Not enough valid shares (faulty: {:?})!", faulty),
+            Error::NotOwned => fmt.write_str("Chain is not owned by a data-subject!"),
+            Error::SubjectRequired => fmt.write_str("Chain requires the data-subject!"),
+            Error::Mismatch => fmt.write_str("Doesn't match the chain!"),
+            Error::Expired => fmt.write_str("Expired!"),
+            Error::Unauthorized => fmt.write_str("Signer is not an authorized writer!"),
+            Error::UnexpectedSigner => fmt.write_str("File is not signed by the expected source!"),
+            Error::UnknownRequester => fmt.write_str("Requester is not authorized by the curator!"),
+            Error::InvalidParameter(msg) => fmt.write_str(msg),
+            Error::Remote { status, message } => write!(fmt, "Remote error ({}): {}", status, message),
+            Error::Io(e) => write!(fmt, "I/O error: {}", e),
+            Error::Codec(e) => write!(fmt, "Codec error: {}", e)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Codec(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Codec(e)
+    }
+}
+
+// the same variant with or without the "json" feature
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Codec(Box::new(bincode::ErrorKind::Custom(format!("JSON: {}", e))))
+    }
+}
+
+// for the Read/Write implementations
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+        }
+    }
+}
@@ -0,0 +1,157 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use crate::structs::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// Replica comparison
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Clone)]
+pub enum ChainRelation {
+    Equal,
+    Behind(usize), // "ours" is a prefix of "theirs", missing records
+    Ahead(usize), // "theirs" is a prefix of "ours", extra records
+    Fork(Fork)
+}
+
+// both branches after the common prefix, each one signed by its source
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Fork {
+    pub index: usize, // length of the common prefix
+    pub ours: Vec<Rn>,
+    pub theirs: Vec<Rn>
+}
+
+impl Fork {
+    // the first records of both branches extend the same record, it's a misbehaviour if they have the same source
+    pub fn equivocation(&self) -> Option<Equivocation> {
+        let (a, b) = (self.ours.first()?, self.theirs.first()?);
+        let proof = Equivocation { first: a.clone(), second: b.clone() };
+        proof.verify().ok().map(|_| proof)
+    }
+}
+
+// compares two replicas of the same (id, set) chain, the common prefix is found with the Merkle roots
+pub fn compare(ours: &RnChain, theirs: &RnChain) -> Result<ChainRelation> {
+    if ours.id() != theirs.id() || ours.set() != theirs.set() {
+        return Err(Error::Mismatch)
+    }
+
+    // binary search of the longest common prefix, prefixes of equal roots are equal
+    let (a, b) = (ours.tree(), theirs.tree());
+    let (mut lo, mut hi) = (0, std::cmp::min(a.len(), b.len()));
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if a.root_at(mid)? == b.root_at(mid)? {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+
+    let (na, nb) = (ours.chain.len(), theirs.chain.len());
+    let relation = match lo {
+        _ if lo == na && lo == nb => ChainRelation::Equal,
+        _ if lo == na => ChainRelation::Behind(nb - lo),
+        _ if lo == nb => ChainRelation::Ahead(na - lo),
+        index => ChainRelation::Fork(Fork { index, ours: ours.chain[index..].to_vec(), theirs: theirs.chain[index..].to_vec() })
+    };
+
+    Ok(relation)
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Equivocation (proof of misbehaviour, a source signed two different records on the same hprev)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Equivocation {
+    pub first: Rn,
+    pub second: Rn
+}
+
+impl Equivocation {
+    pub fn source(&self) -> &RistrettoPoint {
+        self.first.owner()
+    }
+
+    // verifiable by anyone, without the chains
+    pub fn verify(&self) -> Result<()> {
+        let (a, b) = (&self.first, &self.second);
+        if a.check()? == b.check()? || a.owner() != b.owner() {
+            return Err(Error::Mismatch)
+        }
+
+        // two heads of the same chain or two tails on the same record
+        let same = match (&a.hprev, &b.hprev) {
+            (Some(ha), Some(hb)) => ha == hb,
+            (None, None) => a.id == b.id && a.set == b.set,
+            _ => false
+        };
+
+        if !same {
+            return Err(Error::Mismatch)
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::*;
+
+    fn rd(hfile: &str) -> RnData {
+        RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: hfile.as_bytes().to_vec() } }
+    }
+
+    #[test]
+    fn fork_equivocation() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+        let (id, set) = ("subject-id", "dataset-id");
+
+        let (_, head) = Rn::head(&skp, &ekp.key, id, set, rd("file-0-url"));
+        let mut ours = RnChain::new(head).unwrap();
+        let mut theirs = ours.clone();
+        for i in 1..3 {
+            let (_, r) = Rn::tail(&skp, &ekp.key, &ours.lhash, id, set, rd(&format!("file-{}-url", i)));
+            ours.push(r.clone()).unwrap();
+            theirs.push(r).unwrap();
+        }
+
+        assert!(matches!(compare(&ours, &theirs).unwrap(), ChainRelation::Equal));
+
+        // the source appends different tails to each replica
+        let (_, r) = Rn::tail(&skp, &ekp.key, &ours.lhash, id, set, rd("file-3-url"));
+        ours.push(r).unwrap();
+        assert!(matches!(compare(&ours, &theirs).unwrap(), ChainRelation::Ahead(1)));
+        assert!(matches!(compare(&theirs, &ours).unwrap(), ChainRelation::Behind(1)));
+
+        let (_, r) = Rn::tail(&skp, &ekp.key, &theirs.lhash, id, set, rd("other-url"));
+        theirs.push(r).unwrap();
+
+        let fork = match compare(&ours, &theirs).unwrap() {
+            ChainRelation::Fork(fork) => fork,
+            _ => panic!("fork not detected")
+        };
+
+        assert!(fork.index == 3 && fork.ours.len() == 1 && fork.theirs.len() == 1);
+        let proof = fork.equivocation().unwrap();
+        assert!(proof.verify().is_ok() && proof.source() == &skp.key);
+
+        // a different source on the same record is a fork but not an equivocation
+        let other = KeyPair::new();
+        let (_, r) = Rn::tail(&other, &ekp.key, &fork.ours[0].hprev.clone().unwrap(), id, set, rd("other-url"));
+        let fork = Fork { theirs: vec![r], ..fork };
+        assert!(fork.equivocation().is_none());
+
+        let proof = Equivocation { first: proof.first.clone(), second: proof.first };
+        assert!(matches!(proof.verify(), Err(Error::Mismatch)));
+    }
+}
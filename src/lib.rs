@@ -0,0 +1,20 @@
+#![forbid(unsafe_code)]
+
+pub mod cipher;
+#[cfg(feature = "curator")]
+pub mod client;
+pub mod codec;
+pub mod consent;
+pub mod container;
+pub mod crypto;
+#[cfg(feature = "curator")]
+pub mod curator;
+pub mod emergency;
+pub mod errors;
+pub mod fork;
+pub mod merkle;
+pub mod policy;
+pub mod pseudonym;
+pub mod simulator;
+pub mod store;
+pub mod structs;
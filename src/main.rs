@@ -1,17 +1,28 @@
-mod crypto;
-mod structs;
-
 use rand::prelude::*;
 use clap::{Arg, ArgMatches, App, SubCommand};
 use num_format::{Locale, ToFormattedString};
 use std::time::Instant;
+use std::io::Cursor;
+
+use f_pacs::cipher::AesBackend;
+use f_pacs::container::*;
+use f_pacs::crypto::*;
+use f_pacs::crypto::dkg::*;
+use f_pacs::crypto::shares::*;
+use f_pacs::simulator::*;
+use f_pacs::structs::*;
+
+#[cfg(feature = "json")]
+use f_pacs::codec::to_json;
 
-use crate::crypto::*;
-use crate::crypto::shares::*;
-use crate::structs::*;
+#[cfg(feature = "curator")]
+use f_pacs::{codec::from_json, curator::Curator, store::RnStore};
+
+#[cfg(feature = "curator")]
+use curve25519_dalek::ristretto::RistrettoPoint;
 
 fn main() {
-  let matches = App::new("Statistics for Rn/Fn")
+  let app = App::new("Statistics for Rn/Fn")
     .version("1.0")
     .author("Micael Pedrosa <micaelpedrosa@ua.pt>")
     .about("Performs time measurements for Rn/Fn (create/recover)")
@@ -35,8 +46,96 @@ fn main() {
         .help("Select the Fn size in MB")
         .required(true)
         .short("s")
+        .takes_value(true))
+      .arg(Arg::with_name("workers")
+        .help("Sets the number of worker threads (uses the chunked container)")
+        .required(false)
+        .short("w")
         .takes_value(true)))
-    .get_matches();
+
+    .subcommand(SubCommand::with_name("simulate")
+    .about("Runs a deterministic federation scenario in-process")
+      .arg(Arg::with_name("threshold")
+        .help("Sets the threshold number (t)")
+        .required(true)
+        .short("t")
+        .takes_value(true))
+      .arg(Arg::with_name("parties")
+        .help("Sets the number of curators (n)")
+        .required(true)
+        .short("n")
+        .takes_value(true))
+      .arg(Arg::with_name("seed")
+        .help("Seed of the network RNG")
+        .short("s")
+        .takes_value(true))
+      .arg(Arg::with_name("crashed")
+        .help("Number of crashed curators")
+        .long("crashed")
+        .takes_value(true))
+      .arg(Arg::with_name("byzantine")
+        .help("Number of curators answering with wrong shares")
+        .long("byzantine")
+        .takes_value(true))
+      .arg(Arg::with_name("partitioned")
+        .help("Number of curators in a network partition")
+        .long("partitioned")
+        .takes_value(true)));
+
+  // key files need the JSON codec and the curator node the HTTP server
+  #[cfg(feature = "json")]
+  let app = app
+    .subcommand(SubCommand::with_name("setup")
+    .about("Generates the curator key files with a simulated DKG (for testing only, all shares are created in one process)")
+      .arg(Arg::with_name("threshold")
+        .help("Sets the threshold number (t)")
+        .required(true)
+        .short("t")
+        .takes_value(true))
+      .arg(Arg::with_name("parties")
+        .help("Sets the number of curators (n)")
+        .required(true)
+        .short("n")
+        .takes_value(true))
+      .arg(Arg::with_name("output")
+        .help("Output directory for the curator-<i>.json files")
+        .required(true)
+        .short("o")
+        .takes_value(true)));
+
+  #[cfg(feature = "curator")]
+  let app = app
+    .subcommand(SubCommand::with_name("curator")
+    .about("Runs a curator node with the HTTP/JSON API")
+      .arg(Arg::with_name("address")
+        .help("Listen address (e.g. 127.0.0.1:7001)")
+        .required(true)
+        .short("a")
+        .takes_value(true))
+      .arg(Arg::with_name("key")
+        .help("Curator key file generated by setup")
+        .required(true)
+        .short("k")
+        .takes_value(true))
+      .arg(Arg::with_name("dir")
+        .help("Directory of the Rn store")
+        .required(true)
+        .short("d")
+        .takes_value(true))
+      .arg(Arg::with_name("grantee")
+        .help("Base64 key allowed to sign access requests (repeatable)")
+        .short("g")
+        .multiple(true)
+        .number_of_values(1)
+        .takes_value(true))
+      .arg(Arg::with_name("requester")
+        .help("Base64 key allowed to sign emergency requests (repeatable)")
+        .short("r")
+        .multiple(true)
+        .number_of_values(1)
+        .takes_value(true)));
+
+  let matches = app.get_matches();
 
   let skp = KeyPair::new(); // source key-pair
   if matches.is_present("Rn") {
@@ -55,8 +154,65 @@ fn main() {
     let sm = matches.subcommand_matches("Fn").unwrap();
     let size = size_cmd(&sm);
 
-    println!("Fn-Setup: (size: {}MB)", size);
-    fn_adaptor_speed(&skp, size);
+    match sm.value_of("workers") {
+      None => {
+        println!("Fn-Setup: (size: {}MB, cipher: aes-128-cbc/{})", size, AesBackend::detect().name());
+        fn_adaptor_speed(&skp, size);
+      },
+      Some(str_workers) => {
+        let workers = str_workers.parse::<usize>().unwrap();
+        println!("Fn-Setup: (size: {}MB, workers: {}, cipher: aes-128-gcm)", size, workers);
+        fn_container_speed(&skp, size, workers);
+      }
+    }
+
+  } else if matches.is_present("simulate") {
+    let sm = matches.subcommand_matches("simulate").unwrap();
+    let arg = |name: &str| sm.value_of(name).map(|v| v.parse::<usize>().unwrap()).unwrap_or(0);
+
+    let mut cfg = SimConfig::new(arg("seed") as u64, arg("threshold"), arg("parties"));
+    cfg.crashed = arg("crashed");
+    cfg.byzantine = arg("byzantine");
+    cfg.partitioned = arg("partitioned");
+
+    println!("Sim-Setup: (t: {}, n: {}, seed: {}, crashed: {}, byzantine: {}, partitioned: {})", cfg.t, cfg.n, cfg.seed, cfg.crashed, cfg.byzantine, cfg.partitioned);
+    let report = simulate(&cfg).unwrap();
+    println!("Sim-Test - (success: {}, messages: {}, rounds: {}, ticks: {}, faulty: {:?})", report.success, report.messages, report.rounds, report.ticks, report.faulty);
+  }
+
+  #[cfg(feature = "json")]
+  if let Some(sm) = matches.subcommand_matches("setup") {
+    let t = sm.value_of("threshold").unwrap().parse::<usize>().unwrap();
+    let n = sm.value_of("parties").unwrap().parse::<usize>().unwrap();
+    let dir = std::path::Path::new(sm.value_of("output").unwrap());
+
+    // the shares are secret, only readable by the owner and never overwritten
+    std::fs::create_dir_all(dir).unwrap();
+    for output in federation(t, n) {
+      let path = dir.join(format!("curator-{}.json", output.share.i));
+      let mut options = std::fs::OpenOptions::new();
+      options.write(true).create_new(true);
+      #[cfg(unix)]
+      std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+      let mut file = options.open(&path).unwrap();
+      std::io::Write::write_all(&mut file, to_json(&output).unwrap().as_bytes()).unwrap();
+      println!("Curator-Key: {}", path.display());
+    }
+  }
+
+  #[cfg(feature = "curator")]
+  if let Some(sm) = matches.subcommand_matches("curator") {
+    let output: DkgOutput = from_json(&std::fs::read_to_string(sm.value_of("key").unwrap()).unwrap()).unwrap();
+    let store = RnStore::open(sm.value_of("dir").unwrap()).unwrap();
+    let keys = |name: &str| -> Vec<RistrettoPoint> {
+      sm.values_of(name).into_iter().flatten().map(|key| key.to_string().decode().unwrap()).collect()
+    };
+
+    let curator = Curator::new(output, store).unwrap().with_grantees(keys("grantee")).with_requesters(keys("requester"));
+    let node = curator.spawn(sm.value_of("address").unwrap()).unwrap();
+    println!("Curator listening on {}", node.addr());
+    node.join();
   }
 }
 
@@ -73,10 +229,10 @@ fn rnd_dn_key() -> [u8; 16] {
 }
 
 fn rn_chain_speed(skp: &KeyPair, t: usize, n: usize, size: usize) {
-  // master key-pair distributed in "n" shares
-  let ekp = KeyPair::new();
-  let poly = Polynomial::rnd(ekp.s, t);
-  let ei = poly.shares(n);
+  // master key distributed in "n" shares
+  let outputs = federation(t, n);
+  let ekey = outputs[0].key();
+  let ei = ShareVector(outputs.iter().map(|o| o.share.clone()).collect());
 
   // construct a Rn chain
   let id = "subject-id";
@@ -89,14 +245,14 @@ fn rn_chain_speed(skp: &KeyPair, t: usize, n: usize, size: usize) {
       match chain.as_mut() {
         None => {
           let rd = RnData { lambda_prev: None, file: RnFileRef { dn: rnd_dn_key(), hfile: format!("file-url-{:?}", i).into_bytes() } };
-          let (lamb, r) = Rn::head(&skp, &ekp.key, id, set, rd);
+          let (lamb, r) = Rn::head(&skp, &ekey, id, set, rd);
           
           lambda = Some(lamb);
           chain = Some(RnChain::new(r).unwrap());
         },
         Some(chain) => {
           let rd = RnData { lambda_prev: lambda.clone(), file: RnFileRef { dn: rnd_dn_key(), hfile: format!("file-url-{:?}", i).into_bytes() } };
-          let (lamb, r) = Rn::tail(&skp, &ekp.key, &chain.lhash, id, set, rd);
+          let (lamb, r) = chain.tail(&skp, &ekey, rd);
           
           lambda = Some(lamb);
           chain.push(r).unwrap();
@@ -126,18 +282,23 @@ fn rn_chain_speed(skp: &KeyPair, t: usize, n: usize, size: usize) {
   }
 }
 
-fn fn_adaptor_speed(skp: &KeyPair, size: usize) {
-  let dn = rnd_dn_key();
-
+fn rnd_stream(size: usize) -> Vec<u8> {
   // generate a random stream of "size" in MB
-  let mut plaintext1 = Vec::with_capacity(size * 1024 * 1024);
+  let mut data = Vec::with_capacity(size * 1024 * 1024);
   let mut rng = rand::thread_rng();
   for _ in 0..size {
     let mut buf = [0u8; 1024 * 1024]; // generate small chunks to avoid explode the stack memory!
     rng.fill_bytes(&mut buf);
-    plaintext1.extend_from_slice(&buf);
+    data.extend_from_slice(&buf);
   }
 
+  data
+}
+
+fn fn_adaptor_speed(skp: &KeyPair, size: usize) {
+  let dn = rnd_dn_key();
+  let plaintext1 = rnd_stream(size);
+
   let mut plaintext2 = Vec::new();
   let mut ciphertext = Vec::new();
 
@@ -146,7 +307,7 @@ fn fn_adaptor_speed(skp: &KeyPair, size: usize) {
   let encrypt_time = Instant::now() - start;
   
   let start = Instant::now();
-    FnAdaptor::load(&dn, ciphertext.as_slice(), &mut plaintext2).unwrap();
+    FnAdaptor::load(&skp.key, &dn, ciphertext.as_slice(), &mut plaintext2).unwrap();
   let dencrypt_time = Instant::now() - start;
 
   let encrypt_speed = (1000 * size as u128)/encrypt_time.as_millis();
@@ -157,3 +318,26 @@ fn fn_adaptor_speed(skp: &KeyPair, size: usize) {
   // check if the recovered plaintext is correct
   assert!(plaintext1 == plaintext2);
 }
+
+
+fn fn_container_speed(skp: &KeyPair, size: usize, workers: usize) {
+  let dn = rnd_dn_key();
+  let plaintext1 = rnd_stream(size);
+  let mut plaintext2 = Vec::new();
+
+  let start = Instant::now();
+    let ciphertext = FnContainer::save_parallel(skp, &dn, plaintext1.len() as u64, plaintext1.as_slice(), Cursor::new(Vec::new()), workers).unwrap();
+  let encrypt_time = Instant::now() - start;
+
+  let start = Instant::now();
+    FnContainer::load_parallel(&skp.key, &dn, Cursor::new(ciphertext.into_inner()), &mut plaintext2, workers).unwrap();
+  let dencrypt_time = Instant::now() - start;
+
+  let encrypt_speed = (1000 * size as u128)/encrypt_time.as_millis();
+  let dencrypt_speed = (1000 * size as u128)/dencrypt_time.as_millis();
+  println!("Fn-Test - (encrypt: {}MB/s, dencrypt: {}MB/s)",
+    encrypt_speed.to_formatted_string(&Locale::en), dencrypt_speed.to_formatted_string(&Locale::en));
+
+  // check if the recovered plaintext is correct
+  assert!(plaintext1 == plaintext2);
+}
\ No newline at end of file
@@ -0,0 +1,312 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use sha2::{Sha512, Digest};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::{Error, Result};
+
+//-----------------------------------------------------------------------------------------------------------
+// MerkleTree (RFC 6962 tree over the record hashes, leaves are H(0x00, leaf) and nodes H(0x01, left, right))
+// NOTE: only the complete subtrees are kept, any other node of a prefix is rebuilt from O(log n) of them.
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Debug, Default, Clone)]
+pub struct MerkleTree {
+    levels: Vec<Vec<Vec<u8>>> // levels[k][j] is the root of leaves [j*2^k, (j+1)*2^k)
+}
+
+impl MerkleTree {
+    pub fn new() -> Self {
+        Self { levels: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.first().map(|l| l.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, leaf: &[u8]) {
+        let mut node = leaf_hash(leaf);
+        let mut k = 0;
+        loop {
+            if self.levels.len() == k {
+                self.levels.push(Vec::new());
+            }
+
+            let level = &mut self.levels[k];
+            level.push(node);
+            if level.len() % 2 == 1 {
+                break
+            }
+
+            node = node_hash(&level[level.len() - 2], &level[level.len() - 1]);
+            k += 1;
+        }
+    }
+
+    pub fn root(&self) -> Vec<u8> {
+        self.root_at(self.len()).unwrap()
+    }
+
+    // root of the first "size" leaves
+    pub fn root_at(&self, size: usize) -> Result<Vec<u8>> {
+        if size > self.len() {
+            return Err(Error::InvalidParameter("Invalid tree size!"))
+        }
+
+        match size {
+            0 => Ok(Sha512::new().result().to_vec()),
+            _ => Ok(self.subtree(0, size))
+        }
+    }
+
+    // proof for the leaf "index" in the tree of the first "size" leaves
+    pub fn inclusion(&self, index: usize, size: usize) -> Result<InclusionProof> {
+        if index >= size || size > self.len() {
+            return Err(Error::InvalidParameter("Invalid tree index!"))
+        }
+
+        let mut path = Vec::new();
+        self.path(index, 0, size, &mut path);
+        Ok(InclusionProof { index, size, path })
+    }
+
+    // proof that the tree of "first" leaves is a prefix of the tree of "second" leaves
+    pub fn consistency(&self, first: usize, second: usize) -> Result<ConsistencyProof> {
+        if first == 0 || first > second || second > self.len() {
+            return Err(Error::InvalidParameter("Invalid tree size!"))
+        }
+
+        let mut path = Vec::new();
+        if first < second {
+            self.subproof(first, 0, second, true, &mut path);
+        }
+
+        Ok(ConsistencyProof { first, second, path })
+    }
+
+    // root of leaves [start, end), left subtrees are always complete
+    fn subtree(&self, start: usize, end: usize) -> Vec<u8> {
+        let n = end - start;
+        if n.is_power_of_two() && start % n == 0 {
+            let k = n.trailing_zeros() as usize;
+            return self.levels[k][start >> k].clone()
+        }
+
+        let k = split(n);
+        node_hash(&self.subtree(start, start + k), &self.subtree(start + k, end))
+    }
+
+    fn path(&self, m: usize, start: usize, end: usize, path: &mut Vec<Vec<u8>>) {
+        let n = end - start;
+        if n == 1 {
+            return
+        }
+
+        let k = split(n);
+        if m < k {
+            self.path(m, start, start + k, path);
+            path.push(self.subtree(start + k, end));
+        } else {
+            self.path(m - k, start + k, end, path);
+            path.push(self.subtree(start, start + k));
+        }
+    }
+
+    fn subproof(&self, m: usize, start: usize, end: usize, complete: bool, path: &mut Vec<Vec<u8>>) {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                path.push(self.subtree(start, end));
+            }
+
+            return
+        }
+
+        let k = split(n);
+        if m <= k {
+            self.subproof(m, start, start + k, complete, path);
+            path.push(self.subtree(start + k, end));
+        } else {
+            self.subproof(m - k, start + k, end, false, path);
+            path.push(self.subtree(start, start + k));
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Proofs (verified with the algorithms of RFC 9162, section 2.1)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub index: usize,
+    pub size: usize,
+    pub path: Vec<Vec<u8>>
+}
+
+impl InclusionProof {
+    // "leaf" is the record hash (Rn::hash) and "root" the trusted root for the proof size
+    pub fn verify(&self, leaf: &[u8], root: &[u8]) -> bool {
+        if self.index >= self.size {
+            return false
+        }
+
+        let (mut fnode, mut snode) = (self.index, self.size - 1);
+        let mut r = leaf_hash(leaf);
+        for p in self.path.iter() {
+            if snode == 0 {
+                return false
+            }
+
+            if fnode & 1 == 1 || fnode == snode {
+                r = node_hash(p, &r);
+                while fnode & 1 == 0 && fnode != 0 {
+                    fnode >>= 1;
+                    snode >>= 1;
+                }
+            } else {
+                r = node_hash(&r, p);
+            }
+
+            fnode >>= 1;
+            snode >>= 1;
+        }
+
+        snode == 0 && r == root
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyProof {
+    pub first: usize,
+    pub second: usize,
+    pub path: Vec<Vec<u8>>
+}
+
+impl ConsistencyProof {
+    pub fn verify(&self, first_root: &[u8], second_root: &[u8]) -> bool {
+        if self.first == 0 || self.first > self.second {
+            return false
+        }
+
+        if self.first == self.second {
+            return self.path.is_empty() && first_root == second_root
+        }
+
+        // a complete first tree is the starting node of the path
+        let mut path = self.path.iter();
+        let start = match self.first.is_power_of_two() {
+            true => first_root.to_vec(),
+            false => match path.next() {
+                Some(p) => p.clone(),
+                None => return false
+            }
+        };
+
+        let (mut fnode, mut snode) = (self.first - 1, self.second - 1);
+        while fnode & 1 == 1 {
+            fnode >>= 1;
+            snode >>= 1;
+        }
+
+        let (mut fr, mut sr) = (start.clone(), start);
+        for c in path {
+            if snode == 0 {
+                return false
+            }
+
+            if fnode & 1 == 1 || fnode == snode {
+                fr = node_hash(c, &fr);
+                sr = node_hash(c, &sr);
+                while fnode & 1 == 0 && fnode != 0 {
+                    fnode >>= 1;
+                    snode >>= 1;
+                }
+            } else {
+                sr = node_hash(&sr, c);
+            }
+
+            fnode >>= 1;
+            snode >>= 1;
+        }
+
+        snode == 0 && fr == first_root && sr == second_root
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Hashing
+//-----------------------------------------------------------------------------------------------------------
+fn leaf_hash(leaf: &[u8]) -> Vec<u8> {
+    Sha512::new()
+        .chain([0u8])
+        .chain(leaf)
+        .result().to_vec()
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    Sha512::new()
+        .chain([1u8])
+        .chain(left)
+        .chain(right)
+        .result().to_vec()
+}
+
+// largest power of two smaller than n (n > 1)
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reference MTH of RFC 6962
+    fn mth(leaves: &[Vec<u8>]) -> Vec<u8> {
+        match leaves.len() {
+            1 => leaf_hash(&leaves[0]),
+            n => {
+                let k = split(n);
+                node_hash(&mth(&leaves[..k]), &mth(&leaves[k..]))
+            }
+        }
+    }
+
+    #[test]
+    fn merkle_proofs() {
+        let leaves: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 64]).collect();
+        let mut tree = MerkleTree::new();
+        for leaf in leaves.iter() {
+            tree.push(leaf);
+        }
+
+        for size in 1..=leaves.len() {
+            let root = tree.root_at(size).unwrap();
+            assert!(root == mth(&leaves[..size]));
+
+            for index in 0..size {
+                let proof = tree.inclusion(index, size).unwrap();
+                assert!(proof.verify(&leaves[index], &root));
+                assert!(!proof.verify(&leaves[(index + 1) % leaves.len()], &root));
+            }
+
+            for first in 1..=size {
+                let proof = tree.consistency(first, size).unwrap();
+                assert!(proof.verify(&tree.root_at(first).unwrap(), &root));
+                if first < size {
+                    assert!(!proof.verify(&root, &root));
+                }
+            }
+        }
+
+        // proofs for other sizes or indexes don't verify
+        let mut proof = tree.inclusion(5, 20).unwrap();
+        proof.index = 4;
+        assert!(!proof.verify(&leaves[5], &tree.root()));
+        assert!(tree.inclusion(20, 20).is_err() && tree.consistency(0, 5).is_err());
+    }
+}
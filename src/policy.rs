@@ -0,0 +1,160 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use serde::{Serialize, Deserialize};
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use crate::structs::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// PolicyUpdate (signed with the record, see Rn::with_policy)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum PolicyUpdate {
+    Writers(#[serde(with = "crate::crypto::b64::vec")] Vec<RistrettoPoint>), // allow-list, only in the head
+    Delegate(#[serde(with = "crate::crypto::b64")] RistrettoPoint), // new writer, by any writer
+    Revoke(#[serde(with = "crate::crypto::b64")] RistrettoPoint) // by the admin or the writer itself
+}
+
+impl PolicyUpdate {
+    pub fn to_vec(&self) -> Vec<u8> {
+        let (tag, keys) = match self {
+            PolicyUpdate::Writers(keys) => (1u8, keys.as_slice()),
+            PolicyUpdate::Delegate(key) => (2u8, std::slice::from_ref(key)),
+            PolicyUpdate::Revoke(key) => (3u8, std::slice::from_ref(key))
+        };
+
+        let mut data = vec![tag];
+        for key in keys {
+            data.extend_from_slice(key.compress().as_bytes());
+        }
+
+        data
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// WritePolicy (authorized writers of a chain, the head signer is the admin and is always authorized)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct WritePolicy {
+    admin: RistrettoPoint,
+    writers: Vec<RistrettoPoint>
+}
+
+impl WritePolicy {
+    // without an allow-list only the head signer can write
+    pub fn new(head: &Rn) -> Result<Self> {
+        let writers = match &head.policy {
+            None => Vec::new(),
+            Some(PolicyUpdate::Writers(keys)) => keys.clone(),
+            Some(_) => return Err(Error::InvalidParameter("Invalid head policy!"))
+        };
+
+        Ok(Self { admin: *head.owner(), writers })
+    }
+
+    pub fn admin(&self) -> &RistrettoPoint {
+        &self.admin
+    }
+
+    pub fn writers(&self) -> &[RistrettoPoint] {
+        &self.writers
+    }
+
+    pub fn is_writer(&self, key: &RistrettoPoint) -> bool {
+        key == &self.admin || self.writers.contains(key)
+    }
+
+    // checks the tail signer before applying its update
+    pub fn apply(&mut self, tail: &Rn) -> Result<()> {
+        let signer = tail.owner();
+        if !self.is_writer(signer) {
+            return Err(Error::Unauthorized)
+        }
+
+        match &tail.policy {
+            None => (),
+            Some(PolicyUpdate::Delegate(key)) if !self.is_writer(key) => self.writers.push(*key),
+            Some(PolicyUpdate::Delegate(_)) => (),
+            Some(PolicyUpdate::Revoke(key)) => {
+                if key == &self.admin || (signer != &self.admin && signer != key) {
+                    return Err(Error::Unauthorized)
+                }
+
+                self.writers.retain(|w| w != key);
+            },
+            Some(PolicyUpdate::Writers(_)) => return Err(Error::InvalidParameter("Allow-list only in the head!"))
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::*;
+
+    fn rd(hfile: &str) -> RnData {
+        RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: hfile.as_bytes().to_vec() } }
+    }
+
+    #[test]
+    fn policy_writers() {
+        let ekp = KeyPair::new(); // master key-pair
+        let (admin, source, other) = (KeyPair::new(), KeyPair::new(), KeyPair::new());
+        let (id, set) = ("subject-id", "dataset-id");
+
+        let (_, head) = Rn::head(&admin, &ekp.key, id, set, rd("file-0-url"));
+        let head = head.with_policy(&admin, PolicyUpdate::Writers(vec![source.key]));
+        let mut chain = RnChain::new(head).unwrap();
+
+        // allowed and unknown writers
+        let (_, r) = Rn::tail(&source, &ekp.key, &chain.lhash, id, set, rd("file-1-url"));
+        chain.push(r).unwrap();
+
+        let (_, r) = Rn::tail(&other, &ekp.key, &chain.lhash, id, set, rd("file-2-url"));
+        assert!(matches!(chain.push(r), Err(Error::Unauthorized)));
+
+        // the policy is part of the signed record
+        let (_, mut r) = Rn::tail(&source, &ekp.key, &chain.lhash, id, set, rd("file-2-url"));
+        r.policy = Some(PolicyUpdate::Delegate(other.key));
+        assert!(matches!(chain.push(r), Err(Error::InvalidSignature)));
+
+        // delegation by a writer and revocation by the admin
+        let (_, r) = Rn::tail(&source, &ekp.key, &chain.lhash, id, set, rd("file-2-url"));
+        chain.push(r.with_policy(&source, PolicyUpdate::Delegate(other.key))).unwrap();
+
+        // dropping the delegation record also drops the delegation
+        let mut prefix = chain.clone();
+        prefix.truncate(2).unwrap();
+        assert!(prefix.policy().writers() == [source.key] && prefix.tree().len() == 2 && prefix.lhash == prefix.chain[1].hash());
+
+        let (_, r) = Rn::tail(&other, &ekp.key, &chain.lhash, id, set, rd("file-3-url"));
+        let revoke = r.clone().with_policy(&other, PolicyUpdate::Revoke(source.key));
+        assert!(matches!(chain.push(revoke), Err(Error::Unauthorized)));
+        chain.push(r).unwrap();
+
+        let (_, r) = Rn::tail(&admin, &ekp.key, &chain.lhash, id, set, rd("file-4-url"));
+        chain.push(r.with_policy(&admin, PolicyUpdate::Revoke(other.key))).unwrap();
+        assert!(chain.policy().writers() == [source.key]);
+
+        let (_, r) = Rn::tail(&other, &ekp.key, &chain.lhash, id, set, rd("file-5-url"));
+        assert!(matches!(chain.push(r), Err(Error::Unauthorized)));
+
+        // the policy is rebuilt after decoding
+        let data = bincode::serialize(&chain).unwrap();
+        let decoded: RnChain = bincode::deserialize(&data).unwrap();
+        assert!(decoded.policy().writers() == [source.key] && decoded.policy().admin() == &admin.key);
+
+        // and decoding fails for chains with unauthorized tails
+        let (_, r) = Rn::tail(&other, &ekp.key, &chain.lhash, id, set, rd("file-5-url"));
+        let mut forged = chain.clone();
+        forged.lhash = r.hash();
+        forged.chain.push(r);
+        assert!(bincode::deserialize::<RnChain>(&bincode::serialize(&forged).unwrap()).is_err());
+    }
+}
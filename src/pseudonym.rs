@@ -0,0 +1,114 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use sha2::{Sha512, Digest};
+use serde::{Serialize, Deserialize};
+
+use clear_on_drop::clear::Clear;
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+use crate::crypto::*;
+use crate::crypto::shares::*;
+use crate::errors::Result;
+
+//-----------------------------------------------------------------------------------------------------------
+// Pseudonym (stored in the Rn head instead of the (id, set) in cleartext)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Pseudonym {
+    pub id: String,
+    pub set: String
+}
+
+impl Pseudonym {
+    pub fn point(id: &str, set: &str) -> RistrettoPoint {
+        let data: &[&[u8]] = &[id.as_bytes(), &[0u8], set.as_bytes()];
+        RistrettoPoint::hash_from_bytes::<Sha512>(&data.concat())
+    }
+
+    fn new(prf: &RistrettoPoint) -> Self {
+        let prf = prf.compress();
+        let id = Sha512::new().chain("id").chain(prf.as_bytes()).result();
+        let set = Sha512::new().chain("set").chain(prf.as_bytes()).result();
+
+        Self {
+            id: base64::encode_config(&id[0..32], base64::URL_SAFE_NO_PAD),
+            set: base64::encode_config(&set[0..32], base64::URL_SAFE_NO_PAD)
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// BlindedPseudonym (oblivious PRF evaluated jointly by the curators, no single curator can link pseudonyms)
+// NOTE: the PRF key must be a dedicated federation key. Evaluating with the shares of the master key
+// would answer "yi * kn" for any kn and leak alpha.
+//-----------------------------------------------------------------------------------------------------------
+pub struct BlindedPseudonym {
+    r: Scalar,
+    pub point: RistrettoPoint
+}
+
+impl Drop for BlindedPseudonym {
+    fn drop(&mut self) {
+        self.r.clear();
+    }
+}
+
+impl BlindedPseudonym {
+    pub fn new(id: &str, set: &str) -> Self {
+        let r = rnd_scalar();
+        Self { r, point: r * Pseudonym::point(id, set) }
+    }
+
+    // curators answer with "yi * point" and a proof against the commitment of the PRF key
+    pub fn finish(&self, shares: &ProvedRistrettoShareVector, commit: &RistrettoPolynomial) -> Result<Pseudonym> {
+        let prf = shares.recover(&self.point, commit).ok()?;
+
+        Ok(Pseudonym::new(&(self.r.invert() * prf)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::*;
+
+    #[test]
+    fn pseudonym_chain() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        // dedicated PRF key of the federation
+        let poly = Polynomial::rnd(rnd_scalar(), 1);
+        let commit = &poly * &G;
+        let pi = poly.shares(3);
+
+        let bp = BlindedPseudonym::new("subject-id", "dataset-id");
+        let pn = bp.finish(&pi.prove(&bp.point), &commit).unwrap();
+
+        // the blinding changes, but not the pseudonym
+        let bp2 = BlindedPseudonym::new("subject-id", "dataset-id");
+        assert!(bp.point != bp2.point);
+        assert!(bp2.finish(&pi.prove(&bp2.point), &commit).unwrap() == pn);
+
+        let bp3 = BlindedPseudonym::new("subject-id", "other-dataset-id");
+        assert!(bp3.finish(&pi.prove(&bp3.point), &commit).unwrap().id != pn.id);
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (_, r) = Rn::head(&skp, &ekp.key, &pn.id, &pn.set, rd.clone());
+        assert!(r.id.as_ref().unwrap() != "subject-id");
+
+        let chain = RnChain::new(r).unwrap();
+        let alpha = (ekp.s * chain.kn()).compress();
+        assert!(chain.recover(&alpha).unwrap()[0] == rd.file);
+
+        // a lying curator is detected by the proofs
+        let mut answers = pi.prove(&bp.point);
+        answers.0[0].share.Yi += G;
+        answers.0[1].share.Yi += G;
+        assert!(matches!(bp.finish(&answers, &commit), Err(Error::NotEnoughShares { .. })));
+    }
+}
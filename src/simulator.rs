@@ -0,0 +1,308 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+
+use crate::crypto::*;
+use crate::crypto::dkg::*;
+use crate::crypto::shares::*;
+use crate::structs::*;
+
+//-----------------------------------------------------------------------------------------------------------
+// Simulation setup and report
+// NOTE: the seed drives the network (delays, faults, payloads), key material still comes from OsRng.
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub seed: u64,
+    pub t: usize,
+    pub n: usize,
+    pub records: usize, // Rn chain size, each record references one Fn file
+    pub file_size: usize, // in bytes
+    pub max_delay: u64, // message delay in ticks, uniform in [1, max_delay]
+    pub timeout: u64, // client wait per round in ticks
+    pub crashed: usize, // curators that never answer
+    pub byzantine: usize, // curators answering with wrong shares
+    pub partitioned: usize // curators cut from the source and the client, still linked to each other
+}
+
+impl SimConfig {
+    pub fn new(seed: u64, t: usize, n: usize) -> Self {
+        Self { seed, t, n, records: 3, file_size: 4096, max_delay: 10, timeout: 25, crashed: 0, byzantine: 0, partitioned: 0 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimReport {
+    pub success: bool,
+    pub messages: usize, // sent messages, including the dropped ones
+    pub rounds: usize, // client query rounds
+    pub ticks: u64,
+    pub faulty: Vec<u32> // curators detected with invalid shares
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Network (discrete events with FIFO links, node 0 is the source/client and curators are 1..=n)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Clone)]
+enum Message {
+    Append(Box<Rn>),
+    Ack,
+    Query(u32, RistrettoPoint), // round, kn
+    Answer(u32, ProvedRistrettoShare)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Behavior {
+    Correct,
+    Crashed,
+    Byzantine,
+    Partitioned
+}
+
+struct SimCurator {
+    output: DkgOutput,
+    behavior: Behavior,
+    chain: Option<RnChain>
+}
+
+struct Network {
+    rng: StdRng,
+    now: u64,
+    seq: u64,
+    messages: usize,
+    max_delay: u64,
+    links: HashMap<(u32, u32), u64>, // last delivery tick of each link
+    partitioned: HashSet<u32>, // links across the partition drop every message
+    queue: BinaryHeap<Reverse<(u64, u64, u32, u32)>>, // (tick, seq, from, to)
+    payloads: HashMap<u64, Message>
+}
+
+impl Network {
+    fn send(&mut self, from: u32, to: u32, msg: Message) {
+        self.messages += 1;
+        if self.partitioned.contains(&from) != self.partitioned.contains(&to) {
+            return
+        }
+
+        let at = self.now + self.rng.gen_range(1, self.max_delay + 1);
+        let at = std::cmp::max(at, *self.links.get(&(from, to)).unwrap_or(&0));
+        self.links.insert((from, to), at);
+
+        self.seq += 1;
+        self.queue.push(Reverse((at, self.seq, from, to)));
+        self.payloads.insert(self.seq, msg);
+    }
+
+    // next message delivered until the deadline, or until there are no more messages
+    fn next(&mut self, deadline: Option<u64>) -> Option<(u32, u32, Message)> {
+        match (self.queue.peek(), deadline) {
+            (Some(Reverse((at, ..))), Some(deadline)) if *at > deadline => {
+                self.now = deadline;
+                return None
+            },
+            (None, Some(deadline)) => {
+                self.now = std::cmp::max(self.now, deadline);
+                return None
+            },
+            (None, None) => return None,
+            _ => ()
+        }
+
+        let Reverse((at, seq, from, to)) = self.queue.pop().unwrap();
+        self.now = at;
+        Some((from, to, self.payloads.remove(&seq).unwrap()))
+    }
+}
+
+impl SimCurator {
+    fn receive(&mut self, net: &mut Network, from: u32, msg: Message) {
+        let i = self.output.share.i;
+        match (self.behavior, msg) {
+            (Behavior::Crashed, _) => (),
+            (_, Message::Append(rn)) => {
+                let res = match self.chain.as_mut() {
+                    None => RnChain::new(*rn).map(|c| self.chain = Some(c)),
+                    Some(chain) => chain.push(*rn)
+                };
+
+                if res.is_ok() {
+                    net.send(i, from, Message::Ack);
+                }
+            },
+            (behavior, Message::Query(round, kn)) => {
+                // only answers for known chains
+                if self.chain.as_ref().map(|c| c.kn()) != Some(&kn) {
+                    return
+                }
+
+                let mut answer = self.output.share.prove(&kn);
+                if behavior == Behavior::Byzantine {
+                    answer.share.Yi += G;
+                }
+
+                net.send(i, from, Message::Answer(round, answer));
+            },
+            _ => ()
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// simulate (source writes the chain and files, the client recovers them in query rounds)
+//-----------------------------------------------------------------------------------------------------------
+pub fn simulate(cfg: &SimConfig) -> Result<SimReport> {
+    if cfg.n == 0 || cfg.t >= cfg.n || cfg.records == 0 || cfg.max_delay == 0 || cfg.crashed + cfg.byzantine + cfg.partitioned > cfg.n {
+        return Err(Error::InvalidParameter("Invalid simulation parameters!"))
+    }
+
+    let mut rng = StdRng::seed_from_u64(cfg.seed);
+
+    // assign the faults to random curators
+    let mut behaviors = vec![Behavior::Crashed; cfg.crashed];
+    behaviors.extend(vec![Behavior::Byzantine; cfg.byzantine]);
+    behaviors.extend(vec![Behavior::Partitioned; cfg.partitioned]);
+    behaviors.resize(cfg.n, Behavior::Correct);
+    behaviors.shuffle(&mut rng);
+
+    let outputs = federation(cfg.t, cfg.n);
+    let ekey = outputs[0].key();
+    let commit = outputs[0].commit.clone();
+    let mut curators: Vec<SimCurator> = outputs.into_iter().zip(behaviors)
+        .map(|(output, behavior)| SimCurator { output, behavior, chain: None })
+        .collect();
+
+    let partitioned = curators.iter().filter(|c| c.behavior == Behavior::Partitioned).map(|c| c.output.share.i).collect();
+    let mut net = Network { rng: StdRng::seed_from_u64(rng.next_u64()), now: 0, seq: 0, messages: 0, max_delay: cfg.max_delay, links: HashMap::new(), partitioned, queue: BinaryHeap::new(), payloads: HashMap::new() };
+
+    // source: Fn files and the Rn chain
+    let skp = KeyPair::new();
+    let (id, set) = ("subject-id", "dataset-id");
+    let mut files = HashMap::<Vec<u8>, (Vec<u8>, Vec<u8>)>::new(); // hfile -> (plaintext, ciphertext)
+    let mut chain: Option<RnChain> = None;
+    let mut lambda: Option<LambdaKey> = None;
+    for k in 0..cfg.records {
+        let mut dn = [0u8; 16];
+        rng.fill_bytes(&mut dn);
+
+        let mut plain = vec![0u8; cfg.file_size];
+        rng.fill_bytes(&mut plain);
+
+        let mut cipher = Vec::new();
+        FnAdaptor::save(&skp, &dn, plain.as_slice(), &mut cipher)?;
+
+        let hfile = format!("file-{}", k).into_bytes();
+        files.insert(hfile.clone(), (plain, cipher));
+
+        let rd = RnData { lambda_prev: lambda.take(), file: RnFileRef { dn, hfile } };
+        let (lamb, rn) = match chain.as_ref() {
+            None => Rn::head(&skp, &ekey, id, set, rd),
+            Some(c) => c.tail(&skp, &ekey, rd)
+        };
+
+        lambda = Some(lamb);
+        for curator in curators.iter() {
+            net.send(0, curator.output.share.i, Message::Append(Box::new(rn.clone())));
+        }
+
+        match chain.as_mut() {
+            None => chain = Some(RnChain::new(rn)?),
+            Some(c) => c.push(rn)?
+        }
+    }
+
+    // deliver the appends
+    let chain = chain.unwrap();
+    while let Some((from, to, msg)) = net.next(None) {
+        if to != 0 {
+            curators[to as usize - 1].receive(&mut net, from, msg);
+        }
+    }
+
+    // client: query rounds of the missing number of shares
+    let kn = *chain.kn();
+    let mut order: Vec<usize> = (0..cfg.n).collect();
+    order.shuffle(&mut net.rng);
+
+    let mut queried = 0usize;
+    let mut rounds = 0usize;
+    let mut valid = Vec::<RistrettoShare>::new();
+    let mut faulty = Vec::<u32>::new();
+    while valid.len() <= cfg.t && queried < cfg.n {
+        rounds += 1;
+        let missing = std::cmp::min(cfg.t + 1 - valid.len(), cfg.n - queried);
+        for &k in order[queried..queried + missing].iter() {
+            net.send(0, curators[k].output.share.i, Message::Query(rounds as u32, kn));
+        }
+        queried += missing;
+
+        let deadline = net.now + cfg.timeout;
+        while let Some((from, to, msg)) = net.next(Some(deadline)) {
+            match msg {
+                Message::Answer(round, answer) if to == 0 => {
+                    if round as usize != rounds || valid.iter().any(|s| s.i == answer.share.i) {
+                        continue
+                    }
+
+                    if answer.share.i == from && answer.verify(&kn, &commit) {
+                        valid.push(answer.share);
+                    } else {
+                        faulty.push(from);
+                    }
+                },
+                msg if to != 0 => curators[to as usize - 1].receive(&mut net, from, msg),
+                _ => ()
+            }
+        }
+    }
+
+    faulty.sort_unstable();
+    let mut report = SimReport { success: false, messages: net.messages, rounds, ticks: net.now, faulty };
+    if valid.len() <= cfg.t {
+        return Ok(report)
+    }
+
+    // recover the chain and the files
+    let alpha = RistrettoShareVector(valid).recover().compress();
+    let refs = chain.recover(&alpha)?;
+    report.success = refs.iter().all(|r| {
+        let (plain, cipher) = &files[&r.hfile];
+        let mut data = Vec::new();
+        FnAdaptor::load(&skp.key, &r.dn, cipher.as_slice(), &mut data).is_ok() && &data == plain
+    });
+
+    Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulate_faults() {
+        let cfg = SimConfig::new(7, 2, 7);
+        let report = simulate(&cfg).unwrap();
+        assert!(report.success && report.rounds == 1 && report.faulty.is_empty());
+
+        // the same seed gives the same run
+        let again = simulate(&cfg).unwrap();
+        assert!(again.messages == report.messages && again.ticks == report.ticks);
+
+        // up to n - (t + 1) faulty curators are tolerated with more rounds, both byzantine ones are queried with this seed
+        let cfg = SimConfig { crashed: 1, byzantine: 2, partitioned: 1, ..SimConfig::new(12, 2, 7) };
+        let report = simulate(&cfg).unwrap();
+        assert!(report.success && report.faulty.len() == 2);
+
+        let cfg = SimConfig { crashed: 3, byzantine: 2, ..SimConfig::new(11, 2, 7) };
+        let report = simulate(&cfg).unwrap();
+        assert!(!report.success && report.rounds > 1);
+    }
+}
@@ -0,0 +1,330 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+
+use sha2::{Sha512, Digest};
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::structs::*;
+
+const FRAME_HEADER: usize = 4 + 8; // payload length and checksum
+const FRAME_MAX: u32 = 16 * 1024 * 1024;
+
+//-----------------------------------------------------------------------------------------------------------
+// RnStore (one append-only segment file per (id, set) chain)
+//-----------------------------------------------------------------------------------------------------------
+pub struct RnStore {
+    dir: PathBuf
+}
+
+impl RnStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self { dir: dir.as_ref().into() })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, id: &str, set: &str) -> PathBuf {
+        let hash = Sha512::new()
+            .chain(id)
+            .chain([0u8])
+            .chain(set)
+            .result();
+
+        let name = base64::encode_config(&hash[0..32], base64::URL_SAFE_NO_PAD);
+        self.dir.join(format!("{}.rns", name))
+    }
+
+    pub fn contains(&self, id: &str, set: &str) -> bool {
+        self.path(id, set).exists()
+    }
+
+    // the head is written to a temporary file and linked in place, a crash never leaves a torn head
+    pub fn create(&self, head: Rn) -> Result<RnSegment> {
+        let chain = RnChain::new(head)?;
+        let path = self.path(chain.id(), chain.set());
+        let tmp = path.with_extension("rns.tmp");
+
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp)?;
+        let mut segment = RnSegment { file, chain };
+        let res = segment.append(0).and_then(|_| self.link(&tmp, &path));
+        std::fs::remove_file(&tmp).ok();
+        res?;
+
+        // make the new file entry durable
+        File::open(&self.dir).and_then(|d| d.sync_all()).ok();
+        Ok(segment)
+    }
+
+    // a file without a complete frame (torn by an older version) is handled as absent
+    pub fn load(&self, id: &str, set: &str) -> Result<RnSegment> {
+        RnSegment::open(self.path(id, set)).map_err(|e| match e {
+            Error::EmptyChain => Error::Io(std::io::ErrorKind::NotFound.into()),
+            e => e
+        })
+    }
+
+    // fails with AlreadyExists unless the existing file has no complete frame
+    fn link(&self, tmp: &Path, path: &Path) -> Result<()> {
+        match std::fs::hard_link(tmp, path) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let data = std::fs::read(path)?;
+                if !matches!(read_frame(&data, 0), Frame::Incomplete) {
+                    return Err(e.into())
+                }
+
+                std::fs::rename(tmp, path)?;
+                Ok(())
+            },
+            res => Ok(res?)
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// RnSegment (framed records: [len: u32][checksum: 8 bytes of SHA-512][Rn::encode])
+//-----------------------------------------------------------------------------------------------------------
+pub struct RnSegment {
+    file: File,
+    pub chain: RnChain
+}
+
+impl RnSegment {
+    // recovers from a crash by truncating an incomplete last frame, the hash chain is rechecked with Rn::check
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        // only the last frame can be torn, a bad checksum or a frame that fails to decode is corruption
+        let mut records = Vec::<Rn>::new();
+        let mut pos = 0usize;
+        while pos < data.len() {
+            match read_frame(&data, pos) {
+                Frame::Complete(payload, next) => {
+                    records.push(Rn::decode(payload).map_err(|_| Error::Corrupted { offset: pos })?);
+                    pos = next;
+                },
+                Frame::Incomplete => break,
+                Frame::Corrupted => return Err(Error::Corrupted { offset: pos })
+            }
+        }
+
+        // the file is only changed after the chain is validated
+        let mut records = records.into_iter();
+        let head = records.next().ok_or(Error::EmptyChain)?;
+        let mut chain = RnChain::new(head)?;
+        for tail in records {
+            chain.push(tail)?;
+        }
+
+        if pos != data.len() {
+            file.set_len(pos as u64)?;
+            file.sync_all()?;
+        }
+
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self { file, chain })
+    }
+
+    pub fn push(&mut self, tail: Rn) -> Result<()> {
+        let len = self.chain.chain.len();
+        self.chain.push(tail)?;
+
+        // keep the memory chain consistent with the file
+        if let Err(e) = self.append(len) {
+            self.chain.truncate(len)?;
+            return Err(e)
+        }
+
+        Ok(())
+    }
+
+    // a failed write is cut from the file, no partial frame is left behind
+    fn append(&mut self, index: usize) -> Result<()> {
+        let payload = self.chain.chain[index].encode();
+        let checksum = Sha512::digest(&payload);
+
+        let len = self.file.seek(SeekFrom::End(0))?;
+        let frame: &[&[u8]] = &[&(payload.len() as u32).to_le_bytes(), &checksum[0..8], &payload];
+        let res = self.file.write_all(&frame.concat()).and_then(|_| self.file.sync_data());
+        if let Err(e) = res {
+            self.file.set_len(len).and_then(|_| self.file.seek(SeekFrom::Start(len))).ok();
+            return Err(e.into())
+        }
+
+        Ok(())
+    }
+}
+
+enum Frame<'a> {
+    Complete(&'a [u8], usize), // payload and the next position
+    Incomplete, // torn write at the end of the file
+    Corrupted
+}
+
+fn read_frame(data: &[u8], pos: usize) -> Frame<'_> {
+    if data.len() < pos + FRAME_HEADER {
+        return Frame::Incomplete
+    }
+
+    let len = u32::from_le_bytes(*arrayref::array_ref!(data, pos, 4));
+    let start = pos + FRAME_HEADER;
+    if len > FRAME_MAX {
+        return Frame::Corrupted
+    }
+
+    if data.len() < start + len as usize {
+        return Frame::Incomplete
+    }
+
+    let payload = &data[start..start + len as usize];
+    if Sha512::digest(payload)[0..8] != data[pos + 4..start] {
+        return Frame::Corrupted
+    }
+
+    Frame::Complete(payload, start + len as usize)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    fn tmp_store() -> (PathBuf, RnStore) {
+        let name = base64::encode_config(&crate::crypto::rnd_scalar().as_bytes()[0..8], base64::URL_SAFE_NO_PAD);
+        let dir = std::env::temp_dir().join(format!("rn-store-{}", name));
+        let store = RnStore::open(&dir).unwrap();
+        (dir, store)
+    }
+
+    fn rn_data(i: usize, lambda: Option<LambdaKey>) -> RnData {
+        RnData { lambda_prev: lambda, file: RnFileRef { dn: *b"encryption123456", hfile: format!("file-{}-url", i).into_bytes() } }
+    }
+
+    #[test]
+    fn store_append_reopen() {
+        let (dir, store) = tmp_store();
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        let (mut lamb, head) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rn_data(0, None));
+        let mut seg = store.create(head).unwrap();
+        for i in 1..5 {
+            let (l, r) = Rn::tail(&skp, &ekp.key, &seg.chain.lhash, "subject-id", "dataset-id", rn_data(i, Some(lamb)));
+            seg.push(r).unwrap();
+            lamb = l;
+        }
+
+        let lhash = seg.chain.lhash.clone();
+        drop(seg);
+
+        let mut seg = store.load("subject-id", "dataset-id").unwrap();
+        assert!(seg.chain.lhash == lhash && seg.chain.chain.len() == 5);
+
+        let alpha = (ekp.s * seg.chain.kn()).compress();
+        let refs = seg.chain.recover(&alpha).unwrap();
+        assert!(refs[4].hfile == b"file-4-url");
+
+        // records that don't extend the chain are not appended
+        let (_, r) = Rn::tail(&skp, &ekp.key, b"wrong-hash", "subject-id", "dataset-id", rn_data(5, None));
+        assert!(seg.push(r).is_err());
+        drop(seg);
+
+        assert!(store.load("subject-id", "dataset-id").unwrap().chain.chain.len() == 5);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn store_torn_tail() {
+        let (dir, store) = tmp_store();
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        let (lamb, head) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rn_data(0, None));
+        let mut seg = store.create(head).unwrap();
+        let (_, r) = Rn::tail(&skp, &ekp.key, &seg.chain.lhash, "subject-id", "dataset-id", rn_data(1, Some(lamb)));
+        seg.push(r).unwrap();
+        drop(seg);
+
+        // simulate a crash in the middle of an append
+        let path = store.path("subject-id", "dataset-id");
+        let len = std::fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[200, 0, 0, 0, 1, 2, 3]).unwrap();
+        drop(file);
+
+        let seg = store.load("subject-id", "dataset-id").unwrap();
+        assert!(seg.chain.chain.len() == 2);
+        assert!(std::fs::metadata(&path).unwrap().len() == len);
+        drop(seg);
+
+        // a tampered record is not a torn tail
+        let mut data = std::fs::read(&path).unwrap();
+        let k = data.len() - 1;
+        data[k] ^= 1;
+
+        // fix the checksum so that it's not handled as a torn tail
+        let first = FRAME_HEADER + u32::from_le_bytes(*arrayref::array_ref!(data, 0, 4)) as usize;
+        let checksum = Sha512::digest(&data[first + FRAME_HEADER..]);
+        data[first + 4..first + FRAME_HEADER].copy_from_slice(&checksum[0..8]);
+        std::fs::write(&path, &data).unwrap();
+
+        assert!(store.load("subject-id", "dataset-id").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn store_torn_head() {
+        let (dir, store) = tmp_store();
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        // simulate a crash in the middle of the head write
+        let path = store.path("subject-id", "dataset-id");
+        std::fs::write(&path, [200, 0, 0, 0, 1, 2, 3]).unwrap();
+        assert!(matches!(store.load("subject-id", "dataset-id"), Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound));
+
+        let (_, head) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rn_data(0, None));
+        store.create(head.clone()).unwrap();
+        assert!(store.load("subject-id", "dataset-id").unwrap().chain.chain.len() == 1);
+        assert!(!path.with_extension("rns.tmp").exists());
+
+        // a complete head is not replaced
+        assert!(matches!(store.create(head), Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn store_corrupted_frame() {
+        let (dir, store) = tmp_store();
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        let (mut lamb, head) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rn_data(0, None));
+        let mut seg = store.create(head).unwrap();
+        for i in 1..4 {
+            let (l, r) = Rn::tail(&skp, &ekp.key, &seg.chain.lhash, "subject-id", "dataset-id", rn_data(i, Some(lamb)));
+            seg.push(r).unwrap();
+            lamb = l;
+        }
+        drop(seg);
+
+        // a flipped byte in the second frame doesn't drop the later records
+        let path = store.path("subject-id", "dataset-id");
+        let mut data = std::fs::read(&path).unwrap();
+        let first = FRAME_HEADER + u32::from_le_bytes(*arrayref::array_ref!(data, 0, 4)) as usize;
+        data[first + FRAME_HEADER + 10] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+
+        assert!(matches!(store.load("subject-id", "dataset-id"), Err(Error::Corrupted { offset }) if offset == first));
+        assert!(std::fs::read(&path).unwrap() == data);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
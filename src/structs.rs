@@ -7,19 +7,25 @@ use serde::{Serialize, Deserialize};
 use curve25519_dalek::ristretto::{RistrettoPoint, CompressedRistretto};
 
 use std::io::{Read, Write};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use rand::RngCore;
+
+use bincode::Options;
 
 use crypto::aes::KeySize;
-use crypto::aesni::{AesNiEncryptor, AesNiDecryptor};
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{AeadEncryptor, AeadDecryptor};
 use aesstream::{AesWriter, AesReader};
 
+use crate::cipher::AesBackend;
 use crate::crypto::*;
 use crate::crypto::signatures::*;
+use crate::merkle::*;
+use crate::policy::*;
 
-pub type BoxError = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, BoxError>;
-
-#[inline]
-pub fn error(msg: &str) -> BoxError { From::from(msg) }
+pub use crate::errors::{Error, Result};
 
 //-----------------------------------------------------------------------------------------------------------
 // LambdaKey
@@ -62,10 +68,40 @@ pub struct RnFileRef {
     pub hfile: Vec<u8>
 }
 
+// the tree and the policy are not serialized, decoding replays and validates all records
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "RnChainData")]
 pub struct RnChain {
     pub lhash: Vec<u8>, // last Rn hash
-    pub chain: Vec<Rn>
+    pub chain: Vec<Rn>,
+    #[serde(skip)]
+    tree: MerkleTree, // over the record hashes
+    #[serde(skip)]
+    policy: WritePolicy
+}
+
+#[derive(Deserialize)]
+struct RnChainData {
+    lhash: Vec<u8>,
+    chain: Vec<Rn>
+}
+
+impl TryFrom<RnChainData> for RnChain {
+    type Error = Error;
+
+    fn try_from(data: RnChainData) -> Result<Self> {
+        let mut records = data.chain.into_iter();
+        let mut chain = RnChain::new(records.next().ok_or(Error::EmptyChain)?)?;
+        for rn in records {
+            chain.push(rn)?;
+        }
+
+        if chain.lhash != data.lhash {
+            return Err(Error::BrokenChain { index: chain.chain.len() })
+        }
+
+        Ok(chain)
+    }
 }
 
 impl RnChain {
@@ -81,44 +117,198 @@ impl RnChain {
         &self.chain.last().unwrap().data.kn
     }
 
+    pub fn subject(&self) -> Option<&RistrettoPoint> {
+        self.chain.first().unwrap().subject.as_ref()
+    }
+
+    // encryption key for the next tail
+    pub fn ekey(&self, ekey: &RistrettoPoint) -> RistrettoPoint {
+        match self.subject() {
+            Some(subject) => ekey + subject,
+            None => *ekey
+        }
+    }
+
+    // next tail of the chain, encrypted with the chain ekey
+    pub fn tail(&self, keyp: &KeyPair, ekey: &RistrettoPoint, rd: RnData) -> (LambdaKey, Rn) {
+        Rn::tail(keyp, &self.ekey(ekey), &self.lhash, self.id(), self.set(), rd)
+    }
+
     pub fn new(head: Rn) -> Result<Self> {
         let lhash = head.check()?;
         if head.id.is_none() {
-            Err("Record is not a head type!")?
+            return Err(Error::NotHead)
         }
         
-        Ok(Self { lhash, chain: vec![head] })
+        let policy = WritePolicy::new(&head)?;
+        let mut tree = MerkleTree::new();
+        tree.push(&lhash);
+        Ok(Self { lhash, chain: vec![head], tree, policy })
     }
 
     pub fn push(&mut self, tail: Rn) -> Result<()> {
         let dhash = tail.check()?;
 
-        let hprev = tail.hprev.as_ref().ok_or_else(|| error("Record is not a tail type!"))?;
+        let hprev = tail.hprev.as_ref().ok_or(Error::NotTail)?;
         if &self.lhash != hprev {
-            Err("Incorrect hash chain!")?
+            return Err(Error::BrokenChain { index: self.chain.len() })
         }
 
+        // applied to a copy, the chain is unchanged on error
+        let mut policy = self.policy.clone();
+        policy.apply(&tail)?;
+
+        self.policy = policy;
+        self.tree.push(&dhash);
         self.lhash = dhash;
         self.chain.push(tail);
 
         Ok(())
     }
 
+    // keeps the first "len" records, the tree and the policy are rebuilt
+    pub fn truncate(&mut self, len: usize) -> Result<()> {
+        if len == 0 || len > self.chain.len() {
+            return Err(Error::InvalidParameter("Invalid chain length!"))
+        }
+
+        self.chain.truncate(len);
+        self.tree = MerkleTree::new();
+        self.policy = WritePolicy::new(&self.chain[0])?;
+        for (index, rn) in self.chain.iter().enumerate() {
+            self.tree.push(&rn.hash());
+            if index > 0 {
+                self.policy.apply(rn)?;
+            }
+        }
+
+        self.lhash = self.chain[len - 1].hash();
+        Ok(())
+    }
+
+    pub fn policy(&self) -> &WritePolicy {
+        &self.policy
+    }
+
+    // Merkle root of the record hashes
+    pub fn root(&self) -> Vec<u8> {
+        self.tree.root()
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    // record "index" is in this chain, checked with InclusionProof::verify(rn.hash(), root)
+    pub fn inclusion(&self, index: usize) -> Result<InclusionProof> {
+        self.tree.inclusion(index, self.chain.len())
+    }
+
+    // this chain extends the first "size" records
+    pub fn consistency(&self, size: usize) -> Result<ConsistencyProof> {
+        self.tree.consistency(size, self.chain.len())
+    }
+
     pub fn recover(&self, alpha: &CompressedRistretto) -> Result<Vec<RnFileRef>> {
-        let id = self.id();
-        let set = self.set();
+        self.recover_last(alpha, self.chain.len())
+    }
 
-        let mut lambda = Some(LambdaKey::new(alpha, id, set));
+    // only decrypts the last "k" records
+    pub fn recover_last(&self, alpha: &CompressedRistretto, k: usize) -> Result<Vec<RnFileRef>> {
+        let mut chain = self.iter_rev(alpha).take(k).map(|data| data.map(|d| d.file)).collect::<Result<Vec<_>>>()?;
+        chain.reverse();
+        Ok(chain)
+    }
+
+    // decrypts backwards until a record matches the predicate (included)
+    pub fn recover_until<P: FnMut(&RnData) -> bool>(&self, alpha: &CompressedRistretto, mut pred: P) -> Result<Vec<RnFileRef>> {
         let mut chain = Vec::<RnFileRef>::new();
-        for rn in self.chain.iter().rev() {
-            let data = rn.data.data(&lambda.as_ref().unwrap())?;
-            lambda = data.lambda_prev;
+        for data in self.iter_rev(alpha) {
+            let data = data?;
+            let stop = pred(&data);
             chain.push(data.file);
+
+            if stop {
+                break
+            }
         }
 
         chain.reverse();
         Ok(chain)
     }
+
+    // record keys of the range, the grantee can read these records but not walk backward (RN_V3 records only)
+    pub fn grant(&self, alpha: &CompressedRistretto, range: Range<usize>) -> Result<Vec<RecordKey>> {
+        if range.start > range.end || range.end > self.chain.len() {
+            return Err(Error::InvalidParameter("Invalid grant range!"))
+        }
+
+        let (id, set) = (self.id(), self.set());
+        let mut keys = Vec::<RecordKey>::new();
+        let mut lambda = Some(LambdaKey::new(alpha, id, set));
+        for index in (range.start..self.chain.len()).rev() {
+            let lamb = lambda.take().ok_or(Error::BrokenChain { index })?;
+            let data = &self.chain[index].data;
+            if index < range.end {
+                let link = data.link(&lamb, id, set)?;
+                keys.push(RecordKey { index, key: link.rho });
+                lambda = link.lambda_prev;
+            } else {
+                lambda = data.data(&lamb, id, set)?.lambda_prev;
+            }
+        }
+
+        keys.reverse();
+        Ok(keys)
+    }
+
+    pub fn open(&self, key: &RecordKey) -> Result<RnFileRef> {
+        let rn = self.chain.get(key.index).ok_or(Error::InvalidParameter("Unknown record!"))?;
+        rn.data.body(&key.key, self.id(), self.set())
+    }
+
+    // lazy iterator from the last record to the head, stops after the first error
+    pub fn iter_rev(&self, alpha: &CompressedRistretto) -> RnChainIter<'_> {
+        RnChainIter { chain: self, index: self.chain.len(), lambda: Some(LambdaKey::new(alpha, self.id(), self.set())) }
+    }
+}
+
+pub struct RnChainIter<'a> {
+    chain: &'a RnChain,
+    index: usize,
+    lambda: Option<LambdaKey>
+}
+
+impl<'a> Iterator for RnChainIter<'a> {
+    type Item = Result<RnData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == 0 {
+            return None
+        }
+
+        // a tail without the link to the previous record
+        self.index -= 1;
+        let lambda = match self.lambda.take() {
+            Some(lambda) => lambda,
+            None => {
+                let index = std::mem::take(&mut self.index);
+                return Some(Err(Error::BrokenChain { index }))
+            }
+        };
+
+        let rn = &self.chain.chain[self.index];
+        match rn.data.data(&lambda, self.chain.id(), self.chain.set()) {
+            Ok(data) => {
+                self.lambda = data.lambda_prev.clone();
+                Some(Ok(data))
+            },
+            Err(e) => {
+                self.index = 0;
+                Some(Err(e))
+            }
+        }
+    }
 }
 
 //-----------------------------------------------------------------------------------------------------------
@@ -130,46 +320,148 @@ pub struct RnData {
     pub file: RnFileRef
 }
 
+pub const RN_V1: u8 = 1; // AES-128-CBC (unauthenticated, only for old records)
+pub const RN_V2: u8 = 2; // AES-256-GCM with (kn, id, set) as associated data
+pub const RN_V3: u8 = 3; // RN_V2 with the file reference under a separate record key (see RnChain::grant)
+
+// key of a single record, doesn't open the backward link
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct RecordKey {
+    pub index: usize,
+    key: [u8; 32]
+}
+
+#[derive(Serialize, Deserialize)]
+struct RnLink {
+    lambda_prev: Option<LambdaKey>,
+    rho: [u8; 32]
+}
+
+#[derive(Serialize, Deserialize)]
+struct RnParts {
+    link: Vec<u8>, // E_{lambda} [RnLink]
+    body: Vec<u8> // E_{rho} [RnFileRef]
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RnEncData {
+    #[serde(with = "crate::crypto::b64")]
     pub kn: RistrettoPoint,
+    pub version: u8,
     data: Vec<u8>
 }
 
 impl RnEncData {
     fn new(ekey: &RistrettoPoint, id: &str, set: &str, cd: &RnData) -> (LambdaKey, Self) {
         let k = rnd_scalar();
+        let kn = k * G;
         let alpha = (k * ekey).compress();
         let lambda = LambdaKey::new(&alpha, id, set);
 
-        // E_{lambda} [kn_prev, dn, hfile]
-        let mut data = Vec::new();
-        {
-            let encryptor = AesNiEncryptor::new(KeySize::KeySize128, lambda.k128());
-            let mut writer = AesWriter::new(&mut data, encryptor).unwrap();
-            let b_cd = bincode::serialize(cd).unwrap();
-            writer.write_all(&b_cd).unwrap();
-        }
+        let mut rho = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut rho);
+
+        // E_{lambda} [kn_prev, rho], E_{rho} [dn, hfile]
+        let aad = Self::aad(&kn, id, set);
+        let link = RnLink { lambda_prev: cd.lambda_prev.clone(), rho };
+        let parts = RnParts {
+            link: Self::seal(lambda.k256(), &aad, &bincode::serialize(&link).unwrap()),
+            body: Self::seal(&rho, &aad, &bincode::serialize(&cd.file).unwrap())
+        };
 
-        (lambda, Self { kn: (k * &G), data })
+        (lambda, Self { kn, version: RN_V3, data: bincode::serialize(&parts).unwrap() })
     }
 
-    fn data(&self, lambda: &LambdaKey) -> Result<RnData> {
+    fn data(&self, lambda: &LambdaKey, id: &str, set: &str) -> Result<RnData> {
         // D_{lambda} [kn_prev, dn, hfile]
         let mut data = Vec::new();
-        {
-            let decryptor = AesNiDecryptor::new(KeySize::KeySize128, lambda.k128());
-            let mut reader = AesReader::new(self.data.as_slice(), decryptor)?;
-            reader.read_to_end(&mut data)?;
+        match self.version {
+            RN_V1 => {
+                let decryptor = AesBackend::detect().decryptor(lambda.k128());
+                let mut reader = AesReader::new(self.data.as_slice(), decryptor)?;
+                reader.read_to_end(&mut data).map_err(|_| Error::DecryptionFailed)?;
+            },
+            RN_V2 => data = Self::open(lambda.k256(), &Self::aad(&self.kn, id, set), &self.data)?,
+            RN_V3 => {
+                let link = self.link(lambda, id, set)?;
+                let file = self.body(&link.rho, id, set)?;
+                return Ok(RnData { lambda_prev: link.lambda_prev, file })
+            },
+            _ => return Err(Error::UnsupportedVersion)
         }
 
         let cd: RnData = bincode::deserialize(&data)?;
         Ok(cd)
     }
 
+    // backward link of RN_V3 records
+    fn link(&self, lambda: &LambdaKey, id: &str, set: &str) -> Result<RnLink> {
+        if self.version != RN_V3 {
+            return Err(Error::UnsupportedVersion)
+        }
+
+        let parts: RnParts = bincode::deserialize(&self.data).map_err(|_| Error::DecryptionFailed)?;
+        let link = Self::open(lambda.k256(), &Self::aad(&self.kn, id, set), &parts.link)?;
+        Ok(bincode::deserialize(&link)?)
+    }
+
+    // file reference of RN_V3 records, only needs the record key
+    fn body(&self, rho: &[u8; 32], id: &str, set: &str) -> Result<RnFileRef> {
+        if self.version != RN_V3 {
+            return Err(Error::UnsupportedVersion)
+        }
+
+        let parts: RnParts = bincode::deserialize(&self.data).map_err(|_| Error::DecryptionFailed)?;
+        let body = Self::open(rho, &Self::aad(&self.kn, id, set), &parts.body)?;
+        Ok(bincode::deserialize(&body)?)
+    }
+
+    // AES-256-GCM as [nonce, cipher, tag]
+    fn seal(key: &[u8; 32], aad: &[u8], plain: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut cipher = vec![0u8; plain.len()];
+        let mut tag = [0u8; 16];
+        let mut encryptor = AesGcm::new(KeySize::KeySize256, key, &nonce, aad);
+        encryptor.encrypt(plain, &mut cipher, &mut tag);
+
+        let data: &[&[u8]] = &[&nonce, &cipher, &tag];
+        data.concat()
+    }
+
+    fn open(key: &[u8; 32], aad: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < 12 + 16 {
+            return Err(Error::DecryptionFailed)
+        }
+
+        let (nonce, rest) = data.split_at(12);
+        let (cipher, tag) = rest.split_at(rest.len() - 16);
+        let mut plain = vec![0u8; cipher.len()];
+
+        let mut decryptor = AesGcm::new(KeySize::KeySize256, key, nonce, aad);
+        if !decryptor.decrypt(cipher, &mut plain, tag) {
+            return Err(Error::DecryptionFailed)
+        }
+
+        Ok(plain)
+    }
+
+    fn aad(kn: &RistrettoPoint, id: &str, set: &str) -> Vec<u8> {
+        let kn_comp = kn.compress();
+        let data: &[&[u8]] = &[kn_comp.as_bytes(), id.as_bytes(), set.as_bytes()];
+        data.concat()
+    }
+
     fn to_vec(&self) -> Vec<u8> {
+        // the version is not part of the hash of old records
         let kn_comp = self.kn.compress();
-        let data: &[&[u8]] = &[kn_comp.as_bytes(), &self.data];
+        let version = [self.version];
+        let data: &[&[u8]] = match self.version {
+            RN_V1 => &[kn_comp.as_bytes(), &self.data],
+            _ => &[kn_comp.as_bytes(), &version, &self.data]
+        };
+
         data.concat()
     }
 }
@@ -177,11 +469,16 @@ impl RnEncData {
 //-----------------------------------------------------------------------------------------------------------
 // Rn structure
 //-----------------------------------------------------------------------------------------------------------
+// envelope of Rn::encode, legacy bincode records start with the Option tag of "id" (0 or 1)
+pub const RN_FORMAT: u8 = 4;
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Rn {
     pub id: Option<String>,
     pub set: Option<String>,
+    #[serde(with = "crate::crypto::b64::option")]
+    pub subject: Option<RistrettoPoint>, // data-subject key of an owned chain (only in the head)
     pub hprev: Option<Vec<u8>>,
+    pub policy: Option<PolicyUpdate>, // writers of the chain (see WritePolicy)
     pub data: RnEncData,
     sig: ExtSignature
 }
@@ -193,86 +490,235 @@ impl Rn {
 
     pub fn head(keyp: &KeyPair, ekey: &RistrettoPoint, id: &str, set: &str, rd: RnData) -> (LambdaKey, Self) {
         let (lambda, data) = RnEncData::new(ekey, id, set, &rd);
-        let dhash = Sha512::new()
-            .chain(id)
-            .chain(set)
-            .chain(data.to_vec())
-            .result();
+        let dhash = Self::head_hash(id, set, None, None, &data);
 
-        let sig = ExtSignature::sign(&keyp.s, keyp.key.clone(), dhash.as_slice());
-        (lambda, Self { id: Some(id.into()), set: Some(set.into()), hprev: None, data, sig })
+        let sig = ExtSignature::sign(&keyp.s, keyp.key, dhash.as_slice());
+        (lambda, Self { id: Some(id.into()), set: Some(set.into()), subject: None, hprev: None, policy: None, data, sig })
+    }
+
+    // alpha = k*(ekey + subject), the curators can't recover the chain without the data-subject.
+    // Tails of an owned chain must be built with RnChain::tail
+    pub fn owned_head(keyp: &KeyPair, ekey: &RistrettoPoint, subject: &RistrettoPoint, id: &str, set: &str, rd: RnData) -> (LambdaKey, Self) {
+        let (lambda, data) = RnEncData::new(&(ekey + subject), id, set, &rd);
+        let dhash = Self::head_hash(id, set, Some(subject), None, &data);
+
+        let sig = ExtSignature::sign(&keyp.s, keyp.key, dhash.as_slice());
+        (lambda, Self { id: Some(id.into()), set: Some(set.into()), subject: Some(*subject), hprev: None, policy: None, data, sig })
     }
 
     pub fn tail(keyp: &KeyPair, ekey: &RistrettoPoint, hprev: &[u8], id: &str, set: &str, rd: RnData) -> (LambdaKey, Self) {
         let (lambda, data) = RnEncData::new(ekey, id, set, &rd);
-        let dhash = Sha512::new()
-            .chain(hprev)
-            .chain(data.to_vec())
-            .result();
+        let dhash = Self::tail_hash(hprev, None, &data);
 
         let sig = ExtSignature::sign(&keyp.s, keyp.key.clone(), dhash.as_slice());
-        (lambda, Self { id: None, set: None, hprev: Some(hprev.into()), data, sig })
+        (lambda, Self { id: None, set: None, subject: None, hprev: Some(hprev.into()), policy: None, data, sig })
+    }
+
+    // attaches a policy update, signed again with the same key-pair
+    pub fn with_policy(mut self, keyp: &KeyPair, policy: PolicyUpdate) -> Self {
+        self.policy = Some(policy);
+        self.sig = ExtSignature::sign(&keyp.s, keyp.key, self.hash().as_slice());
+        self
+    }
+
+    // versioned encoding for storage
+    pub fn encode(&self) -> Vec<u8> {
+        let data: &[&[u8]] = &[&[RN_FORMAT], &bincode::serialize(self).unwrap()];
+        data.concat()
+    }
+
+    // also reads the plain bincode records of the previous layouts, only if the signature is valid
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let options = bincode::DefaultOptions::new().with_fixint_encoding().reject_trailing_bytes();
+        match data.first() {
+            Some(&RN_FORMAT) => Ok(options.deserialize(&data[1..])?),
+            Some(0) | Some(1) => {
+                let legacy = options.deserialize::<RnLegacySubject>(data).map(Rn::from)
+                    .or_else(|_| options.deserialize::<RnLegacyVersion>(data).map(Rn::from))
+                    .or_else(|_| options.deserialize::<RnLegacy>(data).map(Rn::from))?;
+
+                legacy.check()?;
+                Ok(legacy)
+            },
+            Some(_) => Err(Error::UnsupportedVersion),
+            None => Err(Error::Truncated)
+        }
     }
 
     pub fn check(&self) -> Result<Vec<u8>> {
         let dhash = self.hash();
         if !self.sig.verify(&dhash) {
-            Err("Invalid record signature!")?
+            return Err(Error::InvalidSignature)
         }
 
         Ok(dhash)
     }
 
     pub fn hash(&self) -> Vec<u8> {
-        let dhash = match self.id {
-            Some(_) => Sha512::new()
-                .chain(self.id.as_ref().unwrap())
-                .chain(self.set.as_ref().unwrap())
-                .chain(self.data.to_vec())
-                .result(),
-            None => Sha512::new()
-                .chain(self.hprev.as_ref().unwrap())
-                .chain(self.data.to_vec())
-                .result()
-        };
+        match self.id {
+            Some(_) => Self::head_hash(self.id.as_ref().unwrap(), self.set.as_ref().unwrap(), self.subject.as_ref(), self.policy.as_ref(), &self.data),
+            None => Self::tail_hash(self.hprev.as_ref().unwrap(), self.policy.as_ref(), &self.data)
+        }
+    }
+
+    fn head_hash(id: &str, set: &str, subject: Option<&RistrettoPoint>, policy: Option<&PolicyUpdate>, data: &RnEncData) -> Vec<u8> {
+        let mut hasher = Sha512::new()
+            .chain(id)
+            .chain(set);
+
+        if let Some(subject) = subject {
+            hasher = hasher.chain(subject.compress().as_bytes());
+        }
+
+        if let Some(policy) = policy {
+            hasher = hasher.chain(policy.to_vec());
+        }
+
+        hasher.chain(data.to_vec()).result().to_vec()
+    }
+
+    fn tail_hash(hprev: &[u8], policy: Option<&PolicyUpdate>, data: &RnEncData) -> Vec<u8> {
+        let mut hasher = Sha512::new().chain(hprev);
+        if let Some(policy) = policy {
+            hasher = hasher.chain(policy.to_vec());
+        }
+
+        hasher.chain(data.to_vec()).result().to_vec()
+    }
+}
+
+// bincode layouts without the RN_FORMAT prefix, tried from the newest to the oldest by Rn::decode
+// {id, set, subject, hprev, data, sig}: no policy field, data of any RN_V* version
+#[derive(Deserialize)]
+struct RnLegacySubject {
+    id: Option<String>,
+    set: Option<String>,
+    subject: Option<RistrettoPoint>,
+    hprev: Option<Vec<u8>>,
+    data: RnEncData,
+    sig: ExtSignature
+}
+
+// {id, set, hprev, data, sig}: no subject, data of any RN_V* version
+#[derive(Deserialize)]
+struct RnLegacyVersion {
+    id: Option<String>,
+    set: Option<String>,
+    hprev: Option<Vec<u8>>,
+    data: RnEncData,
+    sig: ExtSignature
+}
+
+// {id, set, hprev, kn, data, sig}: no version in the encrypted data, always RN_V1 (AES-128-CBC)
+#[derive(Deserialize)]
+struct RnLegacy {
+    id: Option<String>,
+    set: Option<String>,
+    hprev: Option<Vec<u8>>,
+    kn: RistrettoPoint,
+    data: Vec<u8>,
+    sig: ExtSignature
+}
+
+impl From<RnLegacySubject> for Rn {
+    fn from(rn: RnLegacySubject) -> Self {
+        Self { id: rn.id, set: rn.set, subject: rn.subject, hprev: rn.hprev, policy: None, data: rn.data, sig: rn.sig }
+    }
+}
 
-        dhash.to_vec()
+impl From<RnLegacyVersion> for Rn {
+    fn from(rn: RnLegacyVersion) -> Self {
+        Self { id: rn.id, set: rn.set, subject: None, hprev: rn.hprev, policy: None, data: rn.data, sig: rn.sig }
+    }
+}
+
+impl From<RnLegacy> for Rn {
+    fn from(rn: RnLegacy) -> Self {
+        let data = RnEncData { kn: rn.kn, version: RN_V1, data: rn.data };
+        Self { id: rn.id, set: rn.set, subject: None, hprev: rn.hprev, policy: None, data, sig: rn.sig }
     }
 }
 
 //-----------------------------------------------------------------------------------------------------------
 // FnAdaptor (read/write)
 //-----------------------------------------------------------------------------------------------------------
+const FN_SIG_SIZE: usize = 136; // NOTE: bincode size for ExtSignature
+pub const FN_ADAPTOR_MAGIC: [u8; 4] = *b"FNA2"; // the first layout E_{dn} [sig(dn), data] had no prefix
+
+// FN_ADAPTOR_MAGIC + E_{dn} [data, sig(H(dn, data))], the signature trailer binds the source to the content
 pub struct FnAdaptor;
 impl FnAdaptor {
     pub fn save<R: Read, W: Write>(keyp: &KeyPair, dn: &[u8; 16], mut from: R, mut to: W) -> Result<()> {
-        let encryptor = AesNiEncryptor::new(KeySize::KeySize128, dn);
+        to.write_all(&FN_ADAPTOR_MAGIC)?;
+        let encryptor = AesBackend::detect().encryptor(dn);
         let mut writer = AesWriter::new(&mut to, encryptor)?;
 
-        // construct and write signature
-        let sig = ExtSignature::sign(&keyp.s, keyp.key.clone(), dn);
+        // encrypt data
+        let mut hasher = Sha512::new().chain(dn);
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = from.read(&mut buf)?;
+            if n == 0 {
+                break
+            }
+
+            hasher.input(&buf[..n]);
+            writer.write_all(&buf[..n])?;
+        }
+
+        // construct and write the signature trailer
+        let sig = ExtSignature::sign(&keyp.s, keyp.key, hasher.result().as_slice());
         let b_sig = bincode::serialize(&sig)?;
         writer.write_all(&b_sig)?;
 
-        // encrypt data 
-        std::io::copy(&mut from, &mut writer)?;
         Ok(())
     }
 
-    pub fn load<R: Read, W: Write>(dn: &[u8; 16], mut from: R, mut to: W) -> Result<()> {
-        let decryptor = AesNiDecryptor::new(KeySize::KeySize128, dn);
+    // NOTE: data is written to "to" before the trailer is checked, the output must be discarded on error
+    // "source" is the expected signer, usually the owner of the record that references the file
+    pub fn load<R: Read, W: Write>(source: &RistrettoPoint, dn: &[u8; 16], mut from: R, mut to: W) -> Result<()> {
+        let mut magic = [0u8; 4];
+        from.read_exact(&mut magic).map_err(|_| Error::Truncated)?;
+        if magic != FN_ADAPTOR_MAGIC {
+            return Err(Error::UnsupportedVersion)
+        }
+
+        let decryptor = AesBackend::detect().decryptor(dn);
         let mut reader = AesReader::new(&mut from, decryptor)?;
 
-        // read and validate signature
-        let mut b_sig = [0u8; 136]; // NOTE: bincode size for ExtSignature
-        reader.read(&mut b_sig)?;
-        let sig: ExtSignature = bincode::deserialize(&b_sig)?;
-        if !sig.verify(dn) {
-            Err("Signature verification failed!")?
+        // dencrypt data, holding back the signature trailer
+        let mut hasher = Sha512::new().chain(dn);
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut tail = Vec::<u8>::with_capacity(FN_SIG_SIZE + buf.len());
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break
+            }
+
+            tail.extend_from_slice(&buf[..n]);
+            if tail.len() > FN_SIG_SIZE {
+                let k = tail.len() - FN_SIG_SIZE;
+                hasher.input(&tail[..k]);
+                to.write_all(&tail[..k])?;
+                tail.drain(..k);
+            }
+        }
+
+        // read and validate the signature trailer
+        if tail.len() != FN_SIG_SIZE {
+            return Err(Error::Truncated)
+        }
+
+        let sig: ExtSignature = bincode::deserialize(&tail).map_err(|_| Error::InvalidSignature)?;
+        if !sig.verify(hasher.result().as_slice()) {
+            return Err(Error::InvalidSignature)
+        }
+
+        if &sig.key != source {
+            return Err(Error::UnexpectedSigner)
         }
 
-        // dencrypt data
-        std::io::copy(&mut reader, &mut to)?;
         Ok(())
     }
 }
@@ -296,7 +742,91 @@ mod tests {
 
         let alpha = (ekp.s * &r1.data.kn).compress();
         let lambda = LambdaKey::new(&alpha, id, set);
-        let cd2 = r1.data.data(&lambda).unwrap();
+        let cd2 = r1.data.data(&lambda, id, set).unwrap();
+        assert!(cd1 == cd2);
+    }
+
+    #[test]
+    fn record_authentication() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        let id = "subject-id";
+        let set = "dataset-id";
+
+        let cd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+        let (lambda, mut r) = Rn::head(&skp, &ekp.key, id, set, cd);
+
+        // bound to the subject and dataset
+        let err = r.data.data(&lambda, "other-id", set).err().unwrap();
+        assert!(matches!(err, Error::DecryptionFailed));
+
+        // wrong lambda
+        let wrong = LambdaKey::new(&(rnd_scalar() * G).compress(), id, set);
+        let err = r.data.data(&wrong, id, set).err().unwrap();
+        assert!(matches!(err, Error::DecryptionFailed));
+
+        // tampered ciphertext
+        r.data.data[20] ^= 1;
+        let err = r.data.data(&lambda, id, set).err().unwrap();
+        assert!(matches!(err, Error::DecryptionFailed));
+    }
+
+    // bincode records written by the previous layouts, master secret is [7u8; 32]
+    const LEGACY_VERSION_HEAD: &str = "010a000000000000007375626a6563742d6964010a00000000000000646174617365742d6964002000000000000000d6d70a35bb70c2c6b77fd091da4941bd9762d2ff654c636396c9327063a96071023f0000000000000002a75e8ac60655b222c632c1364ab0af69c883320355366cb7e1aebf33cd4113dfd3a9e15822a672400dafe17e17129d16550e09a6969c0f8e8790b279599758000000000000006c306247533031344a696e417a4a2b633450494d72566753626f57366f623270644d476b72554a635541776c564c447561304c3555477741587a732f575a56394e3161624f4e6b697a49317135414c6855366f5842413d3d2000000000000000b2c194300045b54c21e4f00864d83275387e205a35c00294d35f7bb645aa1367";
+    const LEGACY_VERSION_TAIL: &str = "00000140000000000000005d1fc2a9f2d048ccc31d5c093ca2c668d3a87f89196482477ff2fe557447c4d702eb1c9efd02485cf739a8ff6785d2c5ceeea86c13db91f088b8959455240b452000000000000000fa4c68f8b7dec337cfde795585a85a78cef290d4d6c21df4a19612629e1e7e40028700000000000000e05c63e1675466de31162005a5a665a73d6782ade23c3d48c3cec94fe8eede8e20d71ed8dc595a2edb782e319852165bc5fffca7591fd6de47381e0fd04cf8aed69445a105703ab585e56f4e46235fc70e32b8f7c81c1889dd29c3c03a69cb9aabaeed024edfd87bdbf364d4c258d17876a57026b489047adb03d87fbceb637af05bf27e4db4ae5800000000000000546f7734476d7131612f654f494f3645596e4155444a6b55476d33615545596b464c3975556743755a416953774778325434414c48566c6771563649354c49717946506a543930637a743550506878613756494f44513d3d2000000000000000b2c194300045b54c21e4f00864d83275387e205a35c00294d35f7bb645aa1367";
+    const LEGACY_SUBJECT_HEAD: &str = "010a000000000000007375626a6563742d6964010a00000000000000646174617365742d696400002000000000000000b29f5a3b40788b3b7b62128ae9adc8d7fb733f1fcd397ba23139c6019a7cd076038b000000000000003d00000000000000fba59536c541989a63e94a62de76019af04559cd5f7eec1be8787ede292e958e971b3bae5e8ee61dc1cfa577473dd03434a9db3286045f4d5afe6667643e00000000000000fe26e46d9fcb757dd2072cf18a2f4170fda995a9af8642eab8dbc9c9c348822579acccfa13719b715099bdcb77ccc8e6cf137c5cf8eb695ee38c533a32f558000000000000006c76394d5871724639633965776f64473852303243725a554a62686f465a6b39382f616a574c6f4442414544326e4e7a435854374a3832755651654659654a4b68514177686a654651655551617a56715a332f4e44413d3d2000000000000000885be30f5a7edefce1158dd3bdb5e24bba8173e70a0874a5b680828fb0a05216";
+    const LEGACY_SUBJECT_TAIL: &str = "00000001400000000000000010c8240f82531a2eec7f893788714abcf12a05e943abecd904f44f2d95827e5ab2a30e4769e3e84628fb09de00f2e54ed7414ccf32ab9ce3840c02b69a502a83200000000000000022fba5410c53f046a7ff986b2e07bacd6fd780c2071f811467d9361661db7a5603d3000000000000008500000000000000ddc8cb6ca71df775b090c0ffe963ec19d0fe0e1ff4e91e82b4a6c95d5edd0e5b354b4da44ce862ba0d458a3095abe277190f42bc9bb4a115cff0cc2da671c02aeaa30bd5228edd4c6c752ec6094a90bc4e6787a34c2a665606dddfac7c227356616bcfc466ab69973bb167eccff00f3195e52c01f5b6127f5cb1e6a5f8764e122e40b6f4a93e000000000000006abb7a92316c86cc9e679263d4996b723eac597df1b389849baa73f30ad48bba9fefab8779e91754ed01692476828fba2e1c49f9df01be63910682209f2e580000000000000044455761345748654e366e4f477145544961704b6e4b64686b5739727478473242454f56554733564351584b4833685936326d6e574e545132776d5634574a62563758576b766b4749457a39415a3731664a755741773d3d2000000000000000885be30f5a7edefce1158dd3bdb5e24bba8173e70a0874a5b680828fb0a05216";
+
+    #[test]
+    fn record_legacy_decode() {
+        let s = curve25519_dalek::scalar::Scalar::from_bytes_mod_order([7u8; 32]);
+        let hex = |h: &str| (0..h.len()).step_by(2).map(|i| u8::from_str_radix(&h[i..i + 2], 16).unwrap()).collect::<Vec<u8>>();
+
+        for (head, tail) in [(LEGACY_VERSION_HEAD, LEGACY_VERSION_TAIL), (LEGACY_SUBJECT_HEAD, LEGACY_SUBJECT_TAIL)] {
+            let mut chain = RnChain::new(Rn::decode(&hex(head)).unwrap()).unwrap();
+            chain.push(Rn::decode(&hex(tail)).unwrap()).unwrap();
+
+            let refs = chain.recover(&(s * chain.kn()).compress()).unwrap();
+            assert!(refs[0].hfile == b"file-0-url" && refs[1].hfile == b"file-1-url");
+
+            // written again with the envelope
+            let rn = Rn::decode(&chain.chain[1].encode()).unwrap();
+            assert!(rn.hash() == chain.lhash);
+        }
+
+        // a legacy record with a broken signature is not accepted in any layout
+        let mut data = hex(LEGACY_VERSION_TAIL);
+        let k = data.len() - 1;
+        data[k] ^= 1;
+        assert!(Rn::decode(&data).is_err());
+    }
+
+    #[test]
+    fn record_legacy_load() {
+        let ekp = KeyPair::new(); // master key-pair
+
+        let id = "subject-id";
+        let set = "dataset-id";
+
+        // old record format with AES-128-CBC
+        let k = rnd_scalar();
+        let lambda = LambdaKey::new(&(k * ekp.key).compress(), id, set);
+        let cd1 = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-url".to_vec() } };
+
+        let mut data = Vec::new();
+        {
+            let encryptor = AesBackend::detect().encryptor(lambda.k128());
+            let mut writer = AesWriter::new(&mut data, encryptor).unwrap();
+            writer.write_all(&bincode::serialize(&cd1).unwrap()).unwrap();
+        }
+
+        let kn = k * G;
+        let enc = RnEncData { kn, version: RN_V1, data: data.clone() };
+        assert!(enc.to_vec()[32..] == data[..]);
+
+        let alpha = (ekp.s * enc.kn).compress();
+        let cd2 = enc.data(&LambdaKey::new(&alpha, id, set), id, set).unwrap();
         assert!(cd1 == cd2);
     }
 
@@ -335,6 +865,77 @@ mod tests {
         assert!(res == "(dn=encryption123456, hfile=file-1-url)(dn=encryption654321, hfile=file-2-url)(dn=encryption564321, hfile=file-3-url)");
     }
 
+    #[test]
+    fn chain_partial_recover() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-0-url".to_vec() } };
+        let (mut lamb, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd);
+        let mut chain = RnChain::new(r).unwrap();
+        for i in 1..5 {
+            let rd = RnData { lambda_prev: Some(lamb), file: RnFileRef { dn: *b"encryption123456", hfile: format!("file-{}-url", i).into_bytes() } };
+            let (l, r) = Rn::tail(&skp, &ekp.key, &chain.lhash, "subject-id", "dataset-id", rd);
+            chain.push(r).unwrap();
+            lamb = l;
+        }
+
+        // the head is not readable, only the full recovery fails
+        chain.chain[0].data.data[20] ^= 1;
+        let alpha = (ekp.s * chain.kn()).compress();
+        assert!(chain.recover(&alpha).is_err());
+
+        let refs = chain.recover_last(&alpha, 2).unwrap();
+        assert!(refs.len() == 2 && refs[0].hfile == b"file-3-url" && refs[1].hfile == b"file-4-url");
+
+        let refs = chain.recover_until(&alpha, |d| d.file.hfile == b"file-2-url").unwrap();
+        assert!(refs.len() == 3 && refs[0].hfile == b"file-2-url");
+
+        let mut iter = chain.iter_rev(&alpha).skip(3);
+        assert!(iter.next().unwrap().unwrap().file.hfile == b"file-1-url");
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+
+        // a tail without the backward link can't reach the previous records
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-5-url".to_vec() } };
+        let (_, r) = Rn::tail(&skp, &ekp.key, &chain.lhash, "subject-id", "dataset-id", rd);
+        chain.push(r).unwrap();
+
+        let alpha = (ekp.s * chain.kn()).compress();
+        let mut iter = chain.iter_rev(&alpha);
+        assert!(iter.next().unwrap().unwrap().file.hfile == b"file-5-url");
+        assert!(matches!(iter.next(), Some(Err(Error::BrokenChain { index: 4 }))));
+        assert!(iter.next().is_none());
+        assert!(matches!(chain.recover(&alpha), Err(Error::BrokenChain { index: 4 })));
+    }
+
+    #[test]
+    fn chain_grant() {
+        let ekp = KeyPair::new(); // master key-pair
+        let skp = KeyPair::new(); // source key-pair
+
+        let rd = RnData { lambda_prev: None, file: RnFileRef { dn: *b"encryption123456", hfile: b"file-0-url".to_vec() } };
+        let (mut lamb, r) = Rn::head(&skp, &ekp.key, "subject-id", "dataset-id", rd);
+        let mut chain = RnChain::new(r).unwrap();
+        for i in 1..4 {
+            let rd = RnData { lambda_prev: Some(lamb), file: RnFileRef { dn: *b"encryption123456", hfile: format!("file-{}-url", i).into_bytes() } };
+            let (l, r) = Rn::tail(&skp, &ekp.key, &chain.lhash, "subject-id", "dataset-id", rd);
+            chain.push(r).unwrap();
+            lamb = l;
+        }
+
+        let alpha = (ekp.s * chain.kn()).compress();
+        let keys = chain.grant(&alpha, 1..3).unwrap();
+        assert!(keys.len() == 2 && keys[0].index == 1 && keys[1].index == 2);
+        assert!(chain.open(&keys[0]).unwrap().hfile == b"file-1-url");
+        assert!(chain.open(&keys[1]).unwrap().hfile == b"file-2-url");
+
+        // a record key doesn't open other records
+        let other = RecordKey { index: 0, ..keys[0].clone() };
+        assert!(matches!(chain.open(&other), Err(Error::DecryptionFailed)));
+        assert!(chain.grant(&alpha, 2..5).is_err());
+    }
+
     #[test]
     fn file_write_load() {
         let dn = b"encryption123456";
@@ -350,8 +951,54 @@ mod tests {
 
         // read data
         let mut plaintext2 = Vec::new();
-        FnAdaptor::load(dn, ciphertext.as_slice(), &mut plaintext2).unwrap();
+        FnAdaptor::load(&skp.key, dn, ciphertext.as_slice(), &mut plaintext2).unwrap();
 
         assert!(plaintext1 == plaintext2);
     }
+
+    #[test]
+    fn file_integrity() {
+        let dn = b"encryption123456";
+        let skp = KeyPair::new(); // source key-pair
+
+        let plaintext = vec![7u8; 1000];
+        let mut ciphertext = Vec::new();
+        FnAdaptor::save(&skp, dn, plaintext.as_slice(), &mut ciphertext).unwrap();
+
+        // modification
+        let mut modified = ciphertext.clone();
+        modified[100] ^= 1;
+        assert!(matches!(FnAdaptor::load(&skp.key, dn, modified.as_slice(), Vec::new()), Err(Error::InvalidSignature)));
+
+        // reordering of blocks
+        let mut reordered = ciphertext.clone();
+        let (a, b) = reordered.split_at_mut(64);
+        a[32..48].swap_with_slice(&mut b[0..16]);
+        assert!(FnAdaptor::load(&skp.key, dn, reordered.as_slice(), Vec::new()).is_err());
+
+        // truncation
+        let truncated = &ciphertext[..ciphertext.len() - 160];
+        assert!(FnAdaptor::load(&skp.key, dn, truncated, Vec::new()).is_err());
+
+        let truncated = &ciphertext[..50];
+        assert!(FnAdaptor::load(&skp.key, dn, truncated, Vec::new()).is_err());
+
+        // re-signed by another source
+        let other = KeyPair::new();
+        let mut resigned = Vec::new();
+        FnAdaptor::save(&other, dn, plaintext.as_slice(), &mut resigned).unwrap();
+        assert!(FnAdaptor::load(&other.key, dn, resigned.as_slice(), Vec::new()).is_ok());
+        assert!(matches!(FnAdaptor::load(&skp.key, dn, resigned.as_slice(), Vec::new()), Err(Error::UnexpectedSigner)));
+
+        // files of the first layout, without the prefix
+        let mut first = Vec::new();
+        {
+            let mut writer = AesWriter::new(&mut first, AesBackend::detect().encryptor(dn)).unwrap();
+            let sig = ExtSignature::sign(&skp.s, skp.key, dn);
+            writer.write_all(&bincode::serialize(&sig).unwrap()).unwrap();
+            writer.write_all(&plaintext).unwrap();
+        }
+
+        assert!(matches!(FnAdaptor::load(&skp.key, dn, first.as_slice(), Vec::new()), Err(Error::UnsupportedVersion)));
+    }
 }
\ No newline at end of file